@@ -0,0 +1,77 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+//The generator and the table types are shared with the interpreter, which uses parts of them that this doesn't
+#[allow(dead_code)]
+#[path = "src/parse_table.rs"]
+mod parse_table;
+#[allow(dead_code)]
+#[path = "src/grammar_generator.rs"]
+mod grammar_generator;
+
+//The dialects built into the binary, as the prefix for their tables and their name in dialects/
+const DIALECTS: &[(&str, &str)] = &[("GEL", "gel"), ("GML", "gml")];
+
+//Turns dialects/name.grammar and dialects/name.tokens into static tables (see parser_setup),
+//failing the build with the generator's report if a grammar has conflicts or a token doesn't work
+fn main() {
+	println!("cargo:rerun-if-changed=src/grammar_generator.rs");
+	println!("cargo:rerun-if-changed=src/parse_table.rs");
+
+	let mut out = String::from("//Made by build.rs from the files in dialects/, change those instead\n");
+	for (prefix, name) in DIALECTS {
+		out.push_str(&dialect_tables(prefix, name));
+	}
+	fs::write(Path::new(&env::var("OUT_DIR").unwrap()).join("dialect_tables.rs"), out).expect("Couldn't write the dialect tables");
+}
+
+fn dialect_tables(prefix: &str, name: &str) -> String {
+	println!("cargo:rerun-if-changed=dialects/{}.grammar", name);
+	println!("cargo:rerun-if-changed=dialects/{}.tokens", name);
+
+	let grammar = fs::read_to_string(format!("dialects/{}.grammar", name)).unwrap_or_else(|_| panic!("Couldn't read dialects/{}.grammar", name));
+	let table = match grammar_generator::grammar_generator(grammar) {
+		Ok(table) => table,
+		Err(error) => panic!("dialects/{}.grammar has a problem!\n{}", name, error),
+	};
+	let tokens = fs::read_to_string(format!("dialects/{}.tokens", name)).unwrap_or_else(|_| panic!("Couldn't read dialects/{}.tokens", name));
+	let tokens = match grammar_generator::token_list_generator(&tokens) {
+		Ok(tokens) => tokens,
+		Err(error) => panic!("dialects/{}.tokens has a problem!\n{}", name, error),
+	};
+
+	let mut out = String::new();
+	//conflicts the generator settled on its own are written down, so a change in them shows up when the tables are diffed
+	for conflict in &table.resolved {
+		for token in &conflict.tokens {
+			out.push_str(&format!("\n//{}: {} on {} takes option {}", name, conflict.rule, token, table.table[&conflict.rule][token] + 1));
+		}
+	}
+	let mut names: Vec<&String> = table.rules.keys().collect();
+	names.sort();
+	out.push_str(&format!("\nstatic {}_RULES: &[(&str, &[&[(&str, bool, bool)]])] = &[\n", prefix));
+	for name in &names {
+		out.push_str(&format!("\t({:?}, &[\n", name));
+		for option in &table.rules[*name] {
+			let tokens: Vec<String> = option.iter().map(|tok| format!("({:?}, {}, {})", tok.value, tok.is_terminal, tok.is_subrule)).collect();
+			out.push_str(&format!("\t\t&[{}],\n", tokens.join(", ")));
+		}
+		out.push_str("\t]),\n");
+	}
+	out.push_str("];\n\n");
+	out.push_str(&format!("static {}_TABLE: &[(&str, &[(&str, usize)])] = &[\n", prefix));
+	for name in &names {
+		let mut row: Vec<(&String, &usize)> = table.table[*name].iter().collect();
+		row.sort();
+		let row: Vec<String> = row.iter().map(|(token, option)| format!("({:?}, {})", token, option)).collect();
+		out.push_str(&format!("\t({:?}, &[{}]),\n", name, row.join(", ")));
+	}
+	out.push_str("];\n\n");
+	out.push_str(&format!("static {}_TOKENS: &[(&str, &str, TokenAction)] = &[\n", prefix));
+	for (name, pattern, action) in &tokens {
+		out.push_str(&format!("\t({:?}, {:?}, TokenAction::{:?}),\n", name, pattern, action));
+	}
+	out.push_str("];\n");
+	return out;
+}
@@ -0,0 +1,88 @@
+use crate::datatypes::*;
+
+//Turns the functions in a Program back into a readable listing, one line per opcode:
+//	index  instruction  destination register  operands  source line
+//Labels are printed where they point, and jumps show which position their label resolves to.
+pub fn disassemble(program: &Program) -> String {
+	let mut names: Vec<&String> = program.functions.keys().collect();
+	names.sort();
+	let mut ret_val = String::new();
+	for name in names {
+		ret_val.push_str(&disassemble_function(name, &program.functions[name]));
+		ret_val.push('\n');
+	}
+	return ret_val;
+}
+
+pub fn disassemble_function(name: &str, function: &Function) -> String {
+	let mut ret_val = String::new();
+	let display_name = if name == "" { "<root>" } else { name };
+	ret_val.push_str(&format!("function {} ({} opcodes, {} labels)\n", display_name, function.opcodes.len(), function.labels.len()));
+	if function.data.return_type != Data::Null || function.data.input_types.len() > 0 {
+		let inputs: Vec<String> = function.data.input_types.iter().map(|input| format_operand(input, function)).collect();
+		ret_val.push_str(&format!("    ; takes ({}) returns {}\n", inputs.join(", "), format_operand(&function.data.return_type, function)));
+	}
+	for (index, op) in function.opcodes.iter().enumerate() {
+		for (label, position) in function.labels.iter().enumerate() {
+			if *position == index {
+				ret_val.push_str(&format!("  L{}:\n", label));
+			}
+		}
+		ret_val.push_str(&format_opcode(index, op, function));
+		ret_val.push('\n');
+	}
+	//labels can point one past the last opcode, which just means "return"
+	for (label, position) in function.labels.iter().enumerate() {
+		if *position >= function.opcodes.len() {
+			ret_val.push_str(&format!("  L{}:\n", label));
+		}
+	}
+	return ret_val;
+}
+
+fn format_opcode(index: usize, op: &Opcode, function: &Function) -> String {
+	let destination = if op.register != 0 { format!("r{} =", op.register) } else { String::new() };
+	let operands: Vec<String> = [&op.data, &op.data2].iter().filter(|data| ***data != Data::Null).map(|data| format_operand(data, function)).collect();
+	let line = if op.line > 0 { format!("; line {}", op.line) } else { String::new() };
+	return format!("    {:>4}  {:<8} {:<14} {:<32} {}", index, destination, op.instruction, operands.join(", "), line).trim_end().to_string();
+}
+
+fn format_operand(data: &Data, function: &Function) -> String {
+	match data {
+		Data::Null => {
+			return "null".to_string();
+		}
+		Data::Register(reg) => {
+			return format!("r{}", reg);
+		}
+		Data::Label(label) => {
+			match function.labels.get(*label) {
+				Some(position) => {
+					return format!("L{} (-> {})", label, position);
+				}
+				None => {
+					return format!("L{} (-> ???)", label);
+				}
+			}
+		}
+		Data::Variable(name) => {
+			return format!("${}", name);
+		}
+		Data::String(value) => {
+			return format!("{:?}", value);
+		}
+		Data::Type(typ) => {
+			return format!("<{}>", typ);
+		}
+		Data::Comma(left, right) => {
+			return format!("({}, {})", format_operand(left, function), format_operand(right, function));
+		}
+		Data::Function(name, args) => {
+			let args: Vec<String> = args.iter().map(|arg| format_operand(arg, function)).collect();
+			return format!("{}({})", name, args.join(", "));
+		}
+		other => {
+			return other.to_string();
+		}
+	}
+}
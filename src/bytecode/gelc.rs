@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::fs;
+use std::rc::Rc;
+use rust_decimal::Decimal;
+use crate::datatypes::*;
+
+//The compiled GEL format (.gelc)
+//Everything is little-endian, and functions are written sorted by name so that
+//compiling the same source twice gives the same bytes (which makes them diffable)
+//	header:   "GELC" magic, u16 version, u8 flags (1 = GML scoping), u32 function count
+//	function: string name, FuncData, u32 label count + u64 per label, u32 opcode count + opcodes
+//	opcode:   string instruction, Data data, Data data2, u32 register, i32 line
+//	string:   u32 length + utf8 bytes
+//	Data:     u8 tag + the variant's fields (see write_data)
+//Bump GELC_VERSION whenever any of this changes, old files get rejected instead of misread
+//	2: ARG takes the parameter and its default, and WITH, WITH_NEXT and RETURN were added
+//	3: the flags byte
+const GELC_MAGIC: &[u8; 4] = b"GELC";
+pub const GELC_VERSION: u16 = 3;
+
+pub fn save_program(program: &Program, path: &str) -> Result<(), String> {
+	return fs::write(path, serialize_program(program)).map_err(|error| format!("Could not write {}: {}", path, error));
+}
+
+pub fn load_program(path: &str) -> Result<Program, String> {
+	let bytes = fs::read(path).map_err(|error| format!("Could not read {}: {}", path, error))?;
+	return deserialize_program(&bytes);
+}
+
+pub fn serialize_program(program: &Program) -> Vec<u8> {
+	let mut out = vec![];
+	out.extend_from_slice(GELC_MAGIC);
+	out.extend_from_slice(&GELC_VERSION.to_le_bytes());
+	out.push(program.gml_scoping as u8);
+	let mut names: Vec<&String> = program.functions.keys().collect();
+	names.sort();
+	write_u32(&mut out, names.len() as u32);
+	for name in names {
+		let function = &program.functions[name];
+		write_string(&mut out, name);
+		write_data(&mut out, &function.data.return_type);
+		write_u32(&mut out, function.data.input_types.len() as u32);
+		for input in &function.data.input_types {
+			write_data(&mut out, input);
+		}
+		let mut optional: Vec<(&String, &Data)> = function.data.optional_types.iter().collect();
+		optional.sort_by(|a, b| a.0.cmp(b.0));
+		write_u32(&mut out, optional.len() as u32);
+		for (arg, typ) in optional {
+			write_string(&mut out, arg);
+			write_data(&mut out, typ);
+		}
+		write_u32(&mut out, function.labels.len() as u32);
+		for label in &function.labels {
+			out.extend_from_slice(&(*label as u64).to_le_bytes());
+		}
+		write_u32(&mut out, function.opcodes.len() as u32);
+		for op in &function.opcodes {
+			write_string(&mut out, &op.instruction);
+			write_data(&mut out, &op.data);
+			write_data(&mut out, &op.data2);
+			write_u32(&mut out, op.register);
+			out.extend_from_slice(&op.line.to_le_bytes());
+		}
+	}
+	return out;
+}
+
+pub fn deserialize_program(bytes: &[u8]) -> Result<Program, String> {
+	let mut reader = Reader{bytes, pos: 0};
+	if reader.take(4)? != GELC_MAGIC {
+		return Err("This is not a compiled GEL file (missing the GELC header)".to_string());
+	}
+	let version = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+	if version != GELC_VERSION {
+		return Err(format!("This compiled GEL file is version {}, but this version of GEL reads version {}. Recompile it from the source file.", version, GELC_VERSION));
+	}
+	let mut program = Program::new();
+	program.gml_scoping = reader.take(1)?[0] & 1 != 0;
+	let function_count = reader.u32()?;
+	for _ in 0..function_count {
+		let name = reader.string()?;
+		let return_type = reader.data()?;
+		let mut input_types = vec![];
+		for _ in 0..reader.u32()? {
+			input_types.push(reader.data()?);
+		}
+		let mut optional_types = HashMap::new();
+		for _ in 0..reader.u32()? {
+			let arg = reader.string()?;
+			optional_types.insert(arg, reader.data()?);
+		}
+		let mut labels = vec![];
+		for _ in 0..reader.u32()? {
+			labels.push(reader.u64()? as usize);
+		}
+		let mut opcodes = vec![];
+		for _ in 0..reader.u32()? {
+			let instruction = reader.string()?;
+			let data = reader.data()?;
+			let data2 = reader.data()?;
+			let register = reader.u32()?;
+			let line = i32::from_le_bytes(reader.take(4)?.try_into().unwrap());
+			opcodes.push(Opcode{instruction, data, data2, register, line});
+		}
+		program.functions.insert(name, Rc::new(Function{data: FuncData{return_type, input_types, optional_types}, opcodes, labels}));
+	}
+	if reader.pos != bytes.len() {
+		return Err(format!("Compiled GEL file has {} unexpected bytes at the end", bytes.len() - reader.pos));
+	}
+	return Ok(program);
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+	out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+	write_u32(out, value.len() as u32);
+	out.extend_from_slice(value.as_bytes());
+}
+
+fn write_decimal(out: &mut Vec<u8>, value: &Decimal) {
+	out.extend_from_slice(&value.serialize());
+}
+
+fn write_data(out: &mut Vec<u8>, data: &Data) {
+	match data {
+		Data::Null => out.push(0),
+		Data::Decimal(value) => {
+			out.push(1);
+			write_decimal(out, value);
+		}
+		Data::Int(value) => {
+			out.push(2);
+			out.extend_from_slice(&value.to_le_bytes());
+		}
+		Data::String(value) => {
+			out.push(3);
+			write_string(out, value);
+		}
+		Data::Color(r, g, b, a) => {
+			out.push(4);
+			for value in [r, g, b, a] {
+				write_decimal(out, value);
+			}
+		}
+		Data::Register(value) => {
+			out.push(5);
+			write_u32(out, *value);
+		}
+		Data::Label(value) => {
+			out.push(6);
+			out.extend_from_slice(&(*value as u64).to_le_bytes());
+		}
+		Data::Variable(value) => {
+			out.push(7);
+			write_string(out, value);
+		}
+		Data::Type(value) => {
+			out.push(8);
+			write_string(out, value);
+		}
+		Data::Object(value) => {
+			out.push(9);
+			out.extend_from_slice(&(*value as u64).to_le_bytes());
+		}
+		Data::Comma(left, right) => {
+			out.push(10);
+			write_data(out, left);
+			write_data(out, right);
+		}
+		Data::Function(name, args) => {
+			out.push(11);
+			write_string(out, name);
+			write_u32(out, args.len() as u32);
+			for arg in args.iter() {
+				write_data(out, arg);
+			}
+		}
+	}
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+		if self.pos + count > self.bytes.len() {
+			return Err(format!("Compiled GEL file ends early (wanted {} bytes at offset {})", count, self.pos));
+		}
+		let slice = &self.bytes[self.pos..self.pos + count];
+		self.pos += count;
+		return Ok(slice);
+	}
+	fn u32(&mut self) -> Result<u32, String> {
+		return Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+	}
+	fn u64(&mut self) -> Result<u64, String> {
+		return Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()));
+	}
+	fn string(&mut self) -> Result<String, String> {
+		let length = self.u32()? as usize;
+		return String::from_utf8(self.take(length)?.to_vec()).map_err(|_| format!("Compiled GEL file has a broken string at offset {}", self.pos));
+	}
+	fn decimal(&mut self) -> Result<Decimal, String> {
+		return Ok(Decimal::deserialize(self.take(16)?.try_into().unwrap()));
+	}
+	fn data(&mut self) -> Result<Data, String> {
+		let tag = self.take(1)?[0];
+		match tag {
+			0 => return Ok(Data::Null),
+			1 => return Ok(Data::Decimal(self.decimal()?)),
+			2 => return Ok(Data::Int(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))),
+			3 => return Ok(Data::String(self.string()?)),
+			4 => return Ok(Data::Color(self.decimal()?, self.decimal()?, self.decimal()?, self.decimal()?)),
+			5 => return Ok(Data::Register(self.u32()?)),
+			6 => return Ok(Data::Label(self.u64()? as usize)),
+			7 => return Ok(Data::Variable(self.string()?)),
+			8 => return Ok(Data::Type(self.string()?)),
+			9 => return Ok(Data::Object(self.u64()? as usize)),
+			10 => return Ok(Data::Comma(Box::new(self.data()?), Box::new(self.data()?))),
+			11 => {
+				let name = self.string()?;
+				let mut args = vec![];
+				for _ in 0..self.u32()? {
+					args.push(self.data()?);
+				}
+				return Ok(Data::Function(name, Box::new(args)));
+			}
+			_ => return Err(format!("Compiled GEL file has an unknown value tag {} at offset {}", tag, self.pos - 1)),
+		}
+	}
+}
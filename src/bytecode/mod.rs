@@ -0,0 +1,2 @@
+pub mod gelc;
+pub mod disassembler;
\ No newline at end of file
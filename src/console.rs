@@ -6,55 +6,200 @@ use macroquad::ui::root_ui;
 use macroquad::ui::hash;
 use macroquad::ui::widgets;
 use regex::Regex;
+use std::time::Instant;
 
-use crate::datatypes::Console;
+use crate::datatypes::{Console, LogEntry, LogLevel};
 use crate::datatypes::Program;
-use crate::interpreter::interpreter::interpret_program;
-use crate::parser::parser_setup::compile;
-use crate::parser::parser_setup::compile_file;
+use crate::interpreter::builtin_functions::{builtin_names, builtin_signatures};
+use crate::console_commands::Commands;
+use crate::console_history::{add_history, end_search, search_step, search_text};
 
 
 
-pub fn console_autocomplete(console: &mut Console, programs: &mut Vec<Program>){
-    if let Some(captures) = Regex::new(r"/\S*").unwrap().captures(&console.console_text) {
-        //go through list of commands that match text (maybe with regex?), if there's a perfect match go to the next alphabetically
-    }else if let Some(captures) = Regex::new(r"/(load) (.*)").unwrap().captures(&console.console_text) {
-        //autocomplete directories and files, in the same way commands get autocompleted
+//How many messages the log keeps to scroll back through
+pub const LOG_LIMIT: usize = 1000;
+//How far back a message that comes in again gets counted as a repeat, so a step and a draw error every frame both collapse
+const REPEAT_WINDOW: usize = 8;
+
+impl Console {
+    pub fn log(&mut self, level: LogLevel, source: Option<String>, text: String) {
+        let time = self.started.get_or_insert_with(Instant::now).elapsed();
+        let start = self.console_log.len().saturating_sub(REPEAT_WINDOW);
+        if let Some(position) = self.console_log[start..].iter().position(|entry| entry.text == text && entry.level == level && entry.source == source) {
+            let mut entry = self.console_log.remove(start + position);
+            entry.repeats += 1;
+            entry.frame = self.frame;
+            entry.time = time;
+            entry.age = 600;
+            self.console_log.push(entry);
+            return;
+        }
+        self.console_log.push(LogEntry{text, level, source, frame: self.frame, time, repeats: 0, age: 600});
+        if self.console_log.len() > LOG_LIMIT {
+            self.console_log.drain(..self.console_log.len() - LOG_LIMIT);
+        }
+    }
+    pub fn info(&mut self, text: String) {
+        self.log(LogLevel::Info, None, text);
+    }
+    pub fn warning(&mut self, text: String) {
+        self.log(LogLevel::Warning, None, text);
+    }
+    pub fn error(&mut self, text: String) {
+        self.log(LogLevel::Error, None, format!("Error: {}", text));
+    }
+    //Whether /filter is letting an entry through. The console's own messages always are, since they answer what was typed.
+    pub fn shows(&self, entry: &LogEntry) -> bool {
+        return match &entry.source {
+            Some(source) => !self.hidden_levels.contains(&entry.level) && !self.hidden_sources.contains(source),
+            None => true,
+        };
+    }
+}
+
+//An entry the way the open console shows it, like [f120 2.00s] test.gel: Error: ... (x30)
+pub fn log_line(entry: &LogEntry) -> String {
+    let mut line = format!("[f{} {:.2}s] ", entry.frame, entry.time.as_secs_f32());
+    if let Some(source) = &entry.source {
+        line += &format!("{}: ", source);
+    }
+    line += &entry.text;
+    if entry.repeats > 0 {
+        line += &format!(" (x{})", entry.repeats + 1);
+    }
+    return line;
+}
+
+fn level_color(level: LogLevel) -> Color {
+    return match level {
+        LogLevel::Info => Color::from_rgba(255, 255, 255, 255),
+        LogLevel::Warning => Color::from_rgba(255, 210, 80, 255),
+        LogLevel::Error => Color::from_rgba(255, 100, 100, 255),
+    };
+}
+
+pub fn console_autocomplete(console: &mut Console, programs: &mut Vec<Program>, commands: &Commands){
+    //Tab again right after filling something in goes on to the next thing that fits
+    if console.completions.len() > 0 && console.console_text == console.completions[console.completion_index] {
+        console.completion_index = (console.completion_index + 1) % console.completions.len();
+    } else {
+        console.completions = completions(&console.console_text, programs, commands);
+        console.completion_index = 0;
+        if console.completions.len() == 0 {
+            return;
+        }
+    }
+    console.console_text = console.completions[console.completion_index].to_owned();
+    root_ui().mouse_down((screen_width() - 10.0, screen_height() - 50.0));
+    //builtins show what arguments they take
+    if let Some(word) = Regex::new(r"[A-Za-z_][A-Za-z0-9_]*$").unwrap().find(&console.console_text) {
+        for signature in builtin_signatures(word.as_str()) {
+            console.info(signature);
+            //hints don't need to hang around as long
+            console.console_log.last_mut().unwrap().age = 200;
+        }
     }
 }
 
-pub fn console_submit(console: &mut Console, programs: &mut Vec<Program>){
-    //look, my muscle memory from NTT isn't going away anytime soon, I might as well accept it.
-    if let Some(captures) = Regex::new(r"/(gel|run|gml) (.*)").unwrap().captures(&console.console_text) {
-        let mut command_program = compile(captures.get(2).unwrap().as_str().to_owned());
-        match interpret_program(&mut command_program, "") {
-            Err(msg) => {
-                console.console_log.push((format!("Error: {}", msg.to_string()), 600));
+//Everything the console text could be filled in to, in the order Tab goes through them:
+//  commands after a /, files and folders after /load and /disasm,
+//  and builtins, functions, global.names and object variables of loaded programs in code after /gel, /run and /gml
+pub fn completions(text: &str, programs: &[Program], commands: &Commands) -> Vec<String> {
+    let mut ret_val = vec![];
+    if Regex::new(r"^/\S*$").unwrap().is_match(text) {
+        for name in commands.names(programs) {
+            if name.starts_with(&text[1..]) {
+                ret_val.push(format!("/{} ", name));
+            }
+        }
+    } else if let Some(captures) = Regex::new(r"^(/(?:load|disasm) )(.*)$").unwrap().captures(text) {
+        let path = captures.get(2).unwrap().as_str();
+        let (dir, prefix) = match path.rfind(|character| character == '/' || character == '\\') {
+            Some(index) => (&path[..index + 1], &path[index + 1..]),
+            None => ("", path),
+        };
+        if let Ok(entries) = std::fs::read_dir(if dir == "" { "." } else { dir }) {
+            let mut names: Vec<String> = entries.filter_map(|entry| entry.ok()).filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !name.starts_with(prefix) {
+                    return None;
+                }
+                if entry.path().is_dir() {
+                    return Some(format!("{}/", name));
+                }
+                return Some(name);
+            }).collect();
+            names.sort();
+            for name in names {
+                ret_val.push(format!("{}{}{}", captures.get(1).unwrap().as_str(), dir, name));
+            }
+        }
+    } else if let Some(word) = Regex::new(r"^/(?:gel|run|gml) .*?([A-Za-z_][A-Za-z0-9_.]*)?$").unwrap().captures(text).and_then(|captures| captures.get(1)) {
+        let start = &text[..word.start()];
+        let mut names: Vec<String> = vec![];
+        match word.as_str().rsplit_once('.') {
+            Some(("global", _)) => {
+                for program in programs {
+                    names.extend(program.globals.keys().map(|name| format!("global.{}", name)));
+                }
             }
-            _ => {
-                for message in &command_program.log {
-                    console.console_log.push((message.to_owned(), 600));
+            Some((owner, _)) => {
+                //instance.name could be any instance's variable
+                for program in programs {
+                    for object in program.objects() {
+                        names.extend(object.data.keys().map(|name| format!("{}.{}", owner, name)));
+                    }
+                    names.extend(program.functions.keys().filter(|name| name.starts_with(&format!("{}.", owner))).cloned());
+                }
+            }
+            None => {
+                names.extend(builtin_names().iter().map(|name| name.to_string()));
+                names.push("global.".to_string());
+                for program in programs {
+                    names.extend(program.functions.keys().filter(|name| *name != "").cloned());
+                    //undeclared variables belong to the program itself
+                    if let Some(object) = program.get_object(1) {
+                        names.extend(object.data.keys().cloned());
+                    }
                 }
             }
         }
+        names.sort();
+        names.dedup();
+        for name in names {
+            if name.starts_with(word.as_str()) {
+                ret_val.push(format!("{}{}", start, name));
+            }
+        }
     }
-    else if let Some(captures) = Regex::new(r"/(load) (.*)").unwrap().captures(&console.console_text) {
-        console.console_history.push(console.console_text.to_owned());
-        //TODO: if the program already exists, replace instead of loading a new copy
-        programs.push(compile_file(captures.get(2).unwrap().as_str()));
+    return ret_val;
+}
+
+pub fn console_submit(console: &mut Console, programs: &mut Vec<Program>, commands: &Commands){
+    //Enter while searching runs the match
+    end_search(console, true);
+    let text = console.console_text.to_owned();
+    if text.starts_with('/') {
+        if let Err(msg) = commands.run(&text, console, programs) {
+            console.error(msg);
+        }
     }
     else{
-        console.console_log.push((console.console_text.to_owned(), 600));
+        console.info(text.to_owned());
     }
-    console.console_history.push(console.console_text.to_owned());
+    add_history(console, &text);
     console.console_text = String::new();
     console.open = false;
 }
 
 pub fn console_log(console: &mut Console, program: &mut Program){
+    for error in &program.errors {
+        console.log(LogLevel::Error, Some(program.name()), error.to_owned());
+    }
     for message in &program.log {
-        console.console_log.push((message.to_owned(), 600));
+        console.log(LogLevel::Info, Some(program.name()), message.to_owned());
     }
+    program.errors = vec![];
     program.log = vec![];
 }
 
@@ -78,28 +223,50 @@ pub fn console_step(console: &mut Console){
     if console.just_opened {
         console.open = true;
         console.index = 0;
+        console.scroll = 0;
         root_ui().mouse_down((screen_width() - 10.0, screen_height() - 50.0));
         console.just_opened = false;
     }
 
     let mut set_console = "";
     if console.open {
+        if (is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl)) && is_key_pressed(KeyCode::R) {
+            search_step(console);
+        }
+        //scrolling back through the log
+        if is_key_pressed(KeyCode::PageUp) {
+            console.scroll += 10;
+        }
+        if is_key_pressed(KeyCode::PageDown) {
+            console.scroll = console.scroll.saturating_sub(10);
+        }
+        let (_, wheel) = mouse_wheel();
+        if wheel > 0.0 {
+            console.scroll += 1;
+        } else if wheel < 0.0 {
+            console.scroll = console.scroll.saturating_sub(1);
+        }
         if is_key_pressed(KeyCode::GraveAccent) {
             console.open = false;
         }
+        //Escape while searching just stops the search
         if is_key_pressed(KeyCode::Escape) {
-            console.open = false;
+            if console.search.is_some() {
+                end_search(console, false);
+            } else {
+                console.open = false;
+            }
         }
-        if is_key_pressed(KeyCode::Up) && console.index < console.console_history.len() {
+        if console.search.is_none() && is_key_pressed(KeyCode::Up) && console.index < console.console_history.len() {
             console.index += 1;
             console.console_text = console.console_history[console.console_history.len() - console.index].to_owned();
             root_ui().mouse_down((screen_width() - 10.0, screen_height() - 50.0));
         }
-        if is_key_pressed(KeyCode::Down) && console.index == 1 {
+        if console.search.is_none() && is_key_pressed(KeyCode::Down) && console.index == 1 {
             console.console_text = String::new();
             console.index -= 1;
         }
-        if is_key_pressed(KeyCode::Down) && console.index > 1 {
+        if console.search.is_none() && is_key_pressed(KeyCode::Down) && console.index > 1 {
             console.console_text = console.console_history[console.console_history.len() - console.index].to_owned();
             console.index -= 1;
         }
@@ -122,90 +289,83 @@ pub fn console_step(console: &mut Console){
         console.console_text = set_console.to_owned();
     }
 
+    for entry in &mut console.console_log {
+        if entry.age > 0 {
+            entry.age -= 1;
+        }
+    }
+
+    root_ui().push_skin(&console_skin);
+    let mut y = screen_height() - 60.0;
     if console.open {
-        root_ui().push_skin(&console_skin);
-        let mut y = screen_height() - 60.0;
-        for (text, ref mut age) in console.console_log.iter_mut().rev() {
-            if *age > 0 {
-                *age = *age - 1;
-            }
-            let mut lines = vec![];
-            for line in text.lines() {
-                let mut line_pos = 0;
-                while line_pos < line.len() {
-                    let mut cut_line = &line[line_pos..];
-                    let TextDimensions { width: w, .. } = measure_text(cut_line, None, 30, 1.0);
-                    if w > screen_width() - 20.0 {
-                        let mut i = line_pos + 1;
-                        loop {
-                            cut_line = &line[line_pos..i];
-                            let TextDimensions { width: w, .. } = measure_text(cut_line, None, 30, 1.0);
-                            i += 1;
-                            if i >= line.len() || w > screen_width() - 20.0 {
-                                i -= 1;
-                                line_pos = i;
-                                break;
-                            }
-                        }
-                    } else {
-                        line_pos = line.len();
-                    }
-                    lines.push(cut_line.to_owned());
-                }
-            }
-            y -= 5.0;
-            for line in lines.iter().rev() {
-                y -= 30.0;
-                let TextDimensions { width: w, .. } = measure_text(line, None, 30, 1.0);
-                draw_rectangle(0.0, y, f32::min(w + 10.0, screen_width()), 30.0, console_background_color);
-                draw_text(line, 5.0, y + 25.0, 30.0, console_text_color);
+        //everything /filter lets through, with stamps and where it came from, scrolled back console.scroll messages
+        let count = console.console_log.iter().filter(|entry| console.shows(entry)).count();
+        console.scroll = console.scroll.min(count.saturating_sub(1));
+        for entry in console.console_log.iter().rev().filter(|entry| console.shows(entry)).skip(console.scroll) {
+            if y < 0.0 {
+                break;
             }
+            y = draw_lines(&wrap_lines(&log_line(entry)), y - 5.0, console_background_color, level_color(entry.level));
         }
         widgets::InputText::new(hash!()).size(vec2(screen_width(), 30.0)).position(vec2(0.0, screen_height() - 60.0)).ui(&mut root_ui(), &mut console.console_text);
-        root_ui().pop_skin();
+        if let Some(search) = search_text(console) {
+            let TextDimensions { width: w, .. } = measure_text(&search, None, 30, 1.0);
+            draw_rectangle(0.0, screen_height() - 30.0, f32::min(w + 10.0, screen_width()), 30.0, console_background_color);
+            draw_text(&search, 5.0, screen_height() - 5.0, 30.0, console_text_color);
+        }
     } else {
-        root_ui().push_skin(&console_skin);
-        let mut y = screen_height() - 60.0;
-        for (text, ref mut age) in console.console_log.iter_mut().rev(){
-            if *age > 0 {
-                let mut background_color = console_background_color.to_owned();
-                background_color.a = (*age as f32) / 200.0;
-                let mut text_color = console_text_color.to_owned();
-                text_color.a = (*age as f32) / 200.0;
-                let mut lines = vec![];
-                for line in text.lines() {
-                    let mut line_pos = 0;
-                    while line_pos < line.len() {
-                        let mut cut_line = &line[line_pos..];
-                        let TextDimensions { width: w, .. } = measure_text(cut_line, None, 30, 1.0);
-                        if w > screen_width() - 20.0 {
-                            let mut i = line_pos + 1;
-                            loop {
-                                cut_line = &line[line_pos..i];
-                                let TextDimensions { width: w, .. } = measure_text(cut_line, None, 30, 1.0);
-                                i += 1;
-                                if i >= line.len() || w > screen_width() - 20.0 {
-                                    i -= 1;
-                                    line_pos = i;
-                                    break;
-                                }
-                            }
-                        } else {
-                            line_pos = line.len();
-                        }
-                        lines.push(cut_line.to_owned());
+        //just the newest messages, fading out
+        for entry in console.console_log.iter().rev().filter(|entry| entry.age > 0 && console.shows(entry)) {
+            if y < 0.0 {
+                break;
+            }
+            let mut background_color = console_background_color.to_owned();
+            background_color.a = f32::min(entry.age as f32 / 200.0, 1.0);
+            let mut text_color = level_color(entry.level);
+            text_color.a = f32::min(entry.age as f32 / 200.0, 1.0);
+            let text = if entry.repeats > 0 { format!("{} (x{})", entry.text, entry.repeats + 1) } else { entry.text.to_owned() };
+            y = draw_lines(&wrap_lines(&text), y - 5.0, background_color, text_color);
+        }
+    }
+    root_ui().pop_skin();
+}
+
+//Splits text into lines that fit across the screen
+fn wrap_lines(text: &str) -> Vec<String> {
+    let mut lines = vec![];
+    for line in text.lines() {
+        let mut line_pos = 0;
+        while line_pos < line.len() {
+            let mut cut_line = &line[line_pos..];
+            let TextDimensions { width: w, .. } = measure_text(cut_line, None, 30, 1.0);
+            if w > screen_width() - 20.0 {
+                let mut i = line_pos + 1;
+                loop {
+                    cut_line = &line[line_pos..i];
+                    let TextDimensions { width: w, .. } = measure_text(cut_line, None, 30, 1.0);
+                    i += 1;
+                    if i >= line.len() || w > screen_width() - 20.0 {
+                        i -= 1;
+                        line_pos = i;
+                        break;
                     }
                 }
-                y -= 5.0;
-                for line in lines.iter().rev() {
-                    y -= 30.0;
-                    let TextDimensions { width: w, .. } = measure_text(line, None, 30, 1.0);
-                    draw_rectangle(0.0, y, f32::min(w + 10.0, screen_width()), 30.0, console_background_color);
-                    draw_text(line, 5.0, y + 25.0, 30.0, console_text_color);
-                }
-                *age = *age - 1;
+            } else {
+                line_pos = line.len();
             }
+            lines.push(cut_line.to_owned());
         }
-        root_ui().pop_skin();
     }
-}
\ No newline at end of file
+    return lines;
+}
+
+//Draws lines upwards with their bottom at y, and gives back where the top ended up
+fn draw_lines(lines: &[String], mut y: f32, background_color: Color, text_color: Color) -> f32 {
+    for line in lines.iter().rev() {
+        y -= 30.0;
+        let TextDimensions { width: w, .. } = measure_text(line, None, 30, 1.0);
+        draw_rectangle(0.0, y, f32::min(w + 10.0, screen_width()), 30.0, background_color);
+        draw_text(line, 5.0, y + 25.0, 30.0, text_color);
+    }
+    return y;
+}
@@ -0,0 +1,309 @@
+use crate::datatypes::{Console, Data, LogLevel, Program};
+use crate::interpreter::interpreter::{interpret_function, interpret_program};
+use regex::Regex;
+use crate::parser::parser_setup::{gml_dialect, compile_file, try_compile, try_compile_file, try_compile_with};
+use crate::bytecode::disassembler::disassemble;
+use crate::hot_reload::{loaded_from, reload_program, restart_program};
+
+//What runs when a command is typed, given everything after the command's name (trimmed)
+pub type Handler = Box<dyn Fn(&str, &mut Console, &mut Vec<Program>, &Commands) -> Result<(), String>>;
+
+pub struct Command {
+    pub name: String,
+    pub aliases: Vec<String>,
+    //shown by /help, like "/unload <n> - stops loaded program n and removes it"
+    pub usage: String,
+    handler: Handler,
+}
+
+//Every /command the console knows. The host adds its own with register, and GEL scripts add theirs with console_command,
+//which are looked up in the loaded programs after these, so a script can't take over /load or /quit.
+pub struct Commands {
+    commands: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new() -> Commands {
+        let mut commands = Commands{commands: vec![]};
+        //look, my muscle memory from NTT isn't going away anytime soon, I might as well accept it.
+        commands.register("gel", &["run"], "/gel <code> - runs GEL code in the program picked with /use, a line that's just a value prints it", |code, console, programs, _| run_code(code, false, console, programs));
+        commands.register("gml", &[], "/gml <code> - runs code in GameMaker compatibility mode, the same way as /gel", |code, console, programs, _| run_code(code, true, console, programs));
+        commands.register("use", &[], "/use [n] - runs /gel and /gml code in loaded program n, or in the last one loaded with no n", select);
+        commands.register("load", &[], "/load <file> - loads and runs a .gel, .gml or .gelc file, or reloads it if it's already loaded", load);
+        commands.register("reload", &[], "/reload [n] - swaps in the newest code of every loaded program, or just program n, keeping what it made", reload);
+        commands.register("restart", &[], "/restart [n] - starts every loaded program, or just program n, over from scratch", restart);
+        commands.register("unload", &[], "/unload <n> - stops loaded program n and removes it", unload);
+        commands.register("list", &["ls"], "/list - lists the loaded programs and their numbers", list);
+        commands.register("disasm", &[], "/disasm [n or file] - shows the opcodes of the last loaded program, loaded program n, or a file", disasm);
+        commands.register("filter", &[], "/filter [info, warning, error, or program] - hides loaded programs' messages of that level or from that program (or its number), or shows them again, with nothing shows everything", filter);
+        commands.register("clear", &["cls"], "/clear - clears the console", |_, console, _, _| {
+            console.console_log.clear();
+            return Ok(());
+        });
+        commands.register("help", &["?"], "/help [command] - lists the commands, or shows how to use one", help);
+        commands.register("quit", &["exit"], "/quit - closes GEL", |_, _, _, _| std::process::exit(0));
+        return commands;
+    }
+
+    //Adds a command, replacing any command that already has that name
+    pub fn register(&mut self, name: &str, aliases: &[&str], usage: &str, handler: impl Fn(&str, &mut Console, &mut Vec<Program>, &Commands) -> Result<(), String> + 'static) {
+        let name = name.trim_start_matches('/').to_string();
+        self.commands.retain(|command| command.name != name);
+        let aliases = aliases.iter().map(|alias| alias.trim_start_matches('/').to_string()).collect();
+        self.commands.push(Command{name, aliases, usage: usage.to_string(), handler: Box::new(handler)});
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Command> {
+        return self.commands.iter().find(|command| command.name == name || command.aliases.iter().any(|alias| alias == name));
+    }
+
+    //Every command's name, including the ones loaded programs added, for Tab to fill in
+    pub fn names(&self, programs: &[Program]) -> Vec<String> {
+        let mut names: Vec<String> = self.commands.iter().map(|command| command.name.to_owned()).collect();
+        for program in programs {
+            names.extend(program.commands.iter().map(|command| command.name.to_owned()));
+        }
+        names.sort();
+        names.dedup();
+        return names;
+    }
+
+    //Runs a line typed into the console that starts with a /
+    pub fn run(&self, text: &str, console: &mut Console, programs: &mut Vec<Program>) -> Result<(), String> {
+        let text = text.trim_start_matches('/');
+        let (name, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        if let Some(command) = self.find(name) {
+            return (command.handler)(rest.trim(), console, programs, self);
+        }
+        for program in programs.iter_mut() {
+            if let Some(command) = program.commands.iter().find(|command| command.name == name).cloned() {
+                //the function might have been misspelled, or gone in a reload
+                if !program.functions.contains_key(&command.function) {
+                    return Err(format!("/{} runs {}, but {} doesn't have a function called that", name, command.function, program.name()));
+                }
+                interpret_function(program, &command.function, vec![Data::String(rest.trim().to_string())])?;
+                return Ok(());
+            }
+        }
+        return Err(format!("There's no /{} command, /help lists them", name));
+    }
+}
+
+//The name console code gets while it runs inside a loaded program, which no function in a script can have
+const CONSOLE_FUNCTION: &str = "/console";
+
+//Runs code as part of the program picked with /use, so it sees that program's globals, functions and objects,
+//and variables it sets without declaring them belong to the program, like its own top level code's do.
+//Functions the code defines are added to the program. With no programs loaded it runs on its own.
+//GEL code only runs in GEL programs and GML code in GML programs.
+fn run_code(code: &str, gml: bool, console: &mut Console, programs: &mut Vec<Program>) -> Result<(), String> {
+    let compile = |code: String| if gml { try_compile_with(code, gml_dialect()) } else { try_compile(code) };
+    //a line that's just a value, like global.score or hp * 2, prints it, the same as = global.score
+    let is_value = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*(\.[A-Za-z_][A-Za-z0-9_]*)*$").unwrap().is_match(code);
+    let mut snippet = if is_value {
+        compile(format!("= {}", code))?
+    } else {
+        match compile(code.to_owned()) {
+            Ok(snippet) => snippet,
+            Err(error) => compile(format!("= {}", code)).map_err(|_| error)?,
+        }
+    };
+
+    let mut standalone = Program::new();
+    standalone.gml_scoping = gml;
+    let program = match console.program {
+        Some(index) => loaded_program(&index.to_string(), programs)?,
+        None => programs.last_mut().unwrap_or(&mut standalone),
+    };
+    //globals and self variables work differently in GML, so the code has to match the program it runs in
+    if program.gml_scoping != gml {
+        let (dialect, other) = if program.gml_scoping { ("GML", "/gml") } else { ("GEL", "/gel") };
+        return Err(format!("{} is a {} program, run code in it with {}", program.name(), dialect, other));
+    }
+    let main = snippet.functions.remove("").unwrap();
+    for (name, function) in snippet.functions {
+        program.functions.insert(name, function);
+    }
+    program.functions.insert(CONSOLE_FUNCTION.to_string(), main);
+    let log_start = program.log.len();
+    let result = interpret_program(program, CONSOLE_FUNCTION);
+    program.functions.remove(CONSOLE_FUNCTION);
+    for message in program.log.drain(log_start..) {
+        console.info(message);
+    }
+    return result.map(|_| ());
+}
+
+fn select(target: &str, console: &mut Console, programs: &mut Vec<Program>, _: &Commands) -> Result<(), String> {
+    if target == "" {
+        console.program = None;
+        console.info("Console code runs in the last loaded program".to_string());
+        return Ok(());
+    }
+    let name = loaded_program(target, programs)?.name();
+    console.program = target.parse::<usize>().ok();
+    console.info(format!("Console code runs in {}", name));
+    return Ok(());
+}
+
+fn load(filename: &str, console: &mut Console, programs: &mut Vec<Program>, _: &Commands) -> Result<(), String> {
+    if filename == "" {
+        return Err("/load needs a file to load".to_string());
+    }
+    //a file that's already loaded gets its new code swapped in instead of loading a second copy
+    match programs.iter_mut().find(|program| loaded_from(program, filename)) {
+        Some(program) => {
+            console.info(reload_program(program)?);
+        }
+        None => {
+            programs.push(compile_file(filename));
+        }
+    }
+    return Ok(());
+}
+
+//No argument goes through every loaded program, a number picks one
+fn each_program(target: &str, console: &mut Console, programs: &mut Vec<Program>, action: fn(&mut Program) -> Result<String, String>) -> Result<(), String> {
+    if target == "" {
+        for program in programs.iter_mut().filter(|program| program.source.is_some()) {
+            match action(program) {
+                Ok(message) => console.info(message),
+                Err(msg) => console.error(msg),
+            }
+        }
+        return Ok(());
+    }
+    let program = loaded_program(target, programs)?;
+    console.info(action(program)?);
+    return Ok(());
+}
+
+fn loaded_program<'a>(target: &str, programs: &'a mut Vec<Program>) -> Result<&'a mut Program, String> {
+    let count = programs.len();
+    return match target.parse::<usize>().ok().and_then(|index| programs.get_mut(index)) {
+        Some(program) => Ok(program),
+        None => Err(format!("There is no loaded program {}, there are {} loaded", target, count)),
+    };
+}
+
+fn reload(target: &str, console: &mut Console, programs: &mut Vec<Program>, _: &Commands) -> Result<(), String> {
+    return each_program(target, console, programs, reload_program);
+}
+
+fn restart(target: &str, console: &mut Console, programs: &mut Vec<Program>, _: &Commands) -> Result<(), String> {
+    return each_program(target, console, programs, restart_program);
+}
+
+fn unload(target: &str, console: &mut Console, programs: &mut Vec<Program>, _: &Commands) -> Result<(), String> {
+    loaded_program(target, programs)?;
+    let index = target.parse::<usize>().unwrap();
+    let program = programs.remove(index);
+    //the program /use picked keeps being picked, even though its number changed
+    console.program = match console.program {
+        Some(selected) if selected == index => None,
+        Some(selected) if selected > index => Some(selected - 1),
+        selected => selected,
+    };
+    console.info(format!("Unloaded {}", program.name()));
+    return Ok(());
+}
+
+fn list(_: &str, console: &mut Console, programs: &mut Vec<Program>, _: &Commands) -> Result<(), String> {
+    if programs.len() == 0 {
+        console.info("No programs are loaded".to_string());
+    }
+    for (index, program) in programs.iter().enumerate() {
+        console.info(format!("{}: {}", index, program.name()));
+    }
+    return Ok(());
+}
+
+fn filter(target: &str, console: &mut Console, programs: &mut Vec<Program>, _: &Commands) -> Result<(), String> {
+    if target == "" {
+        console.hidden_levels.clear();
+        console.hidden_sources.clear();
+        console.info("Showing messages from everything again".to_string());
+        return Ok(());
+    }
+    let level = match target.to_lowercase().trim_end_matches('s') {
+        "info" => Some(LogLevel::Info),
+        "warning" | "warn" => Some(LogLevel::Warning),
+        "error" => Some(LogLevel::Error),
+        _ => None,
+    };
+    if let Some(level) = level {
+        if console.hidden_levels.contains(&level) {
+            console.hidden_levels.retain(|hidden| *hidden != level);
+            console.info(format!("Showing {:?} messages again", level));
+        } else {
+            console.hidden_levels.push(level);
+            console.info(format!("Hiding {:?} messages", level));
+        }
+        return Ok(());
+    }
+
+    let source = match target.parse::<usize>() {
+        Ok(_) => loaded_program(target, programs)?.name(),
+        Err(_) => target.to_string(),
+    };
+    if console.hidden_sources.contains(&source) {
+        console.hidden_sources.retain(|hidden| *hidden != source);
+        console.info(format!("Showing messages from {} again", source));
+    } else {
+        if !programs.iter().any(|program| program.name() == source) {
+            console.warning(format!("{} isn't loaded, hiding its messages anyway", source));
+        }
+        console.hidden_sources.push(source.to_owned());
+        console.info(format!("Hiding messages from {}", source));
+    }
+    return Ok(());
+}
+
+fn disasm(target: &str, console: &mut Console, programs: &mut Vec<Program>, _: &Commands) -> Result<(), String> {
+    //no argument disassembles the last loaded program, a number picks a loaded program, anything else is a file
+    let listing = if target == "" {
+        match programs.last() {
+            Some(program) => disassemble(program),
+            None => return Err("No programs are loaded".to_string()),
+        }
+    } else if target.parse::<usize>().is_ok() {
+        disassemble(loaded_program(target, programs)?)
+    } else {
+        disassemble(&try_compile_file(target)?)
+    };
+    for line in listing.lines() {
+        console.info(line.to_owned());
+    }
+    return Ok(());
+}
+
+fn help(name: &str, console: &mut Console, programs: &mut Vec<Program>, commands: &Commands) -> Result<(), String> {
+    let script_usage = |command: &crate::datatypes::ScriptCommand| if command.usage == "" { format!("/{}", command.name) } else { command.usage.to_owned() };
+    if name == "" {
+        for command in &commands.commands {
+            console.info(command.usage.to_owned());
+        }
+        for program in programs.iter() {
+            for command in &program.commands {
+                console.info(script_usage(command));
+            }
+        }
+        return Ok(());
+    }
+    let name = name.trim_start_matches('/');
+    if let Some(command) = commands.find(name) {
+        console.info(command.usage.to_owned());
+        if command.aliases.len() > 0 {
+            let aliases: Vec<String> = command.aliases.iter().map(|alias| format!("/{}", alias)).collect();
+            console.info(format!("Also called {}", aliases.join(", ")));
+        }
+        return Ok(());
+    }
+    for program in programs.iter() {
+        if let Some(command) = program.commands.iter().find(|command| command.name == name) {
+            console.info(format!("{} (from {})", script_usage(command), program.name()));
+            return Ok(());
+        }
+    }
+    return Err(format!("There's no /{} command, /help lists them", name));
+}
@@ -0,0 +1,102 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::datatypes::{Console, HistorySearch};
+
+//How many lines of history are kept, the oldest are dropped past this
+pub const HISTORY_LIMIT: usize = 1000;
+
+//Where the console history is kept between runs: GEL_HISTORY if it's set, otherwise .gel_history in the user's home folder
+pub fn history_file() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("GEL_HISTORY") {
+        return Some(PathBuf::from(path));
+    }
+    return env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(|home| PathBuf::from(home).join(".gel_history"));
+}
+
+//Starts using the history in path, one line per command, oldest first. A missing file is just an empty history.
+pub fn load_history(console: &mut Console, path: Option<PathBuf>) {
+    if let Some(file) = &path {
+        if let Ok(text) = fs::read_to_string(file) {
+            for line in text.lines() {
+                add_line(&mut console.console_history, line);
+            }
+        }
+    }
+    console.history_file = path;
+}
+
+//Adds a submitted line to the end of the history and saves it. A line that's already in the history moves to the end instead of being there twice.
+pub fn add_history(console: &mut Console, text: &str) {
+    //the history file is one command per line
+    if text.trim() == "" || text.contains('\n') {
+        return;
+    }
+    add_line(&mut console.console_history, text);
+    if let Some(file) = &console.history_file {
+        //not being able to save history shouldn't get in the way of anything, so errors are ignored
+        let _ = save_history(&console.console_history, file);
+    }
+}
+
+fn add_line(history: &mut Vec<String>, text: &str) {
+    history.retain(|line| line != text);
+    history.push(text.to_string());
+    if history.len() > HISTORY_LIMIT {
+        history.drain(..history.len() - HISTORY_LIMIT);
+    }
+}
+
+fn save_history(history: &[String], file: &Path) -> std::io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut text = history.join("\n");
+    text.push('\n');
+    return fs::write(file, text);
+}
+
+//The newest line in the history with query in it, skipping the first skip matches, for Ctrl+R
+pub fn search_history<'a>(history: &'a [String], query: &str, skip: usize) -> Option<&'a String> {
+    return history.iter().rev().filter(|line| line.contains(query)).nth(skip);
+}
+
+//Ctrl+R starts searching the history for what's typed, and pressing it again goes to the next older match
+pub fn search_step(console: &mut Console) {
+    match &mut console.search {
+        Some(search) => {
+            if search_history(&console.console_history, &console.console_text, search.skip + 1).is_some() {
+                search.skip += 1;
+            }
+        }
+        None => {
+            console.search = Some(HistorySearch{original: console.console_text.to_owned(), query: console.console_text.to_owned(), skip: 0});
+        }
+    }
+}
+
+//What the search line shows, like a shell's (reverse-i-search)`query': match
+pub fn search_text(console: &mut Console) -> Option<String> {
+    let search = console.search.as_mut()?;
+    //typing more starts over from the newest match
+    if search.query != console.console_text {
+        search.query = console.console_text.to_owned();
+        search.skip = 0;
+    }
+    return match search_history(&console.console_history, &search.query, search.skip) {
+        Some(line) => Some(format!("(reverse-i-search)`{}': {}", search.query, line)),
+        None => Some(format!("(failed reverse-i-search)`{}'", search.query)),
+    };
+}
+
+//Stops searching, leaving the match as the console text, or what was there before if accept is false or nothing matched
+pub fn end_search(console: &mut Console, accept: bool) {
+    if let Some(search) = console.search.take() {
+        let skip = if search.query == console.console_text { search.skip } else { 0 };
+        let found = search_history(&console.console_history, &console.console_text, skip).cloned();
+        console.console_text = match found {
+            Some(line) if accept => line,
+            _ => search.original,
+        };
+    }
+}
@@ -1,31 +1,14 @@
 use std::fmt;
 use std::collections::HashMap;
+use std::rc::Rc;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
 use derivative::Derivative;
 use futures::executor;
 use macroquad::prelude::load_texture;
 use macroquad::texture::Texture2D;
 use rust_decimal::prelude::*;
 
-#[derive(Clone, Copy)]
-pub enum TokenAction {
-	Keywords,
-	Identity,
-	Bool,
-	HexNum,
-	Newline,
-	Comment,
-	Whitespace,
-}
-
-#[derive(Clone)]
-#[derive(Debug)]
-pub struct GrammarToken {
-	pub is_terminal: bool,
-	pub value: String,
-	pub lookahead: Vec<String>,
-	pub is_subrule: bool
-}
-
 #[derive(Debug)]
 #[derive(Clone)]
 pub struct ASTNode {
@@ -172,36 +155,126 @@ pub struct FuncData {
 	pub optional_types: HashMap<String, Data>,
 }
 
+#[derive(Debug)]
+pub struct Function {
+	pub data: FuncData,
+	pub opcodes: Vec<Opcode>,
+	//Positions in opcodes, indexed by the number inside Data::Label
+	pub labels: Vec<usize>,
+}
+
 #[derive(Debug)]
 #[derive(Default)]
 pub struct Program {
 	pub initialized: bool,
 	pub current_frame: i32,
-	pub functions: HashMap<String, (FuncData, Vec<Opcode>)>,
-	pub labels: Vec<usize>,
+	pub functions: HashMap<String, Rc<Function>>,
 	pub sprites: HashMap<String, SpriteData>,
 	objects: Vec<Object>,
 	objects_sorted: HashMap<String, Vec<usize>>,
 	id_index: usize,
 	context: Vec<usize>,
-	pub log: Vec<String>
+	//Variables written as global.name, shared by everything in the program
+	pub globals: HashMap<String, Data>,
+	//Set for GML code, which gets globals, argument0... and self variables from assigning to undeclared names
+	pub gml_scoping: bool,
+	pub log: Vec<String>,
+	//Compile errors from loading it, kept out of log so the console shows them as errors
+	pub errors: Vec<String>,
+	//The files it was compiled from, if it was loaded from one, so hot reloading knows what to watch
+	pub source: Option<Source>,
+	//Console commands the program added with console_command
+	pub commands: Vec<ScriptCommand>,
 }
 
+//A console command added by a GEL script, which runs function with the rest of the line as argument0
 #[derive(Debug)]
+#[derive(Clone)]
+pub struct ScriptCommand {
+	pub name: String,
+	pub function: String,
+	pub usage: String,
+}
+
+//Where a loaded program came from, so it can be compiled again when its files change
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Source {
+	//The loaded file first, then everything it imports, each with when it was last changed
+	pub files: Vec<(String, Option<SystemTime>)>,
+	//The --dialect it was compiled with, when it wasn't picked by the file's extension
+	pub dialect: Option<String>,
+}
+
+#[derive(Debug)]
+#[derive(Default)]
 pub struct Console {
 	pub open: bool,
 	pub just_opened: bool,
 	pub console_text: String,
-	pub console_log: Vec<(String, u32)>,
+	pub console_log: Vec<LogEntry>,
 	pub console_history: Vec<String>,
-	pub index: usize
+	pub index: usize,
+	//What Tab can fill in, and which one it's on, so pressing it again goes to the next one
+	pub completions: Vec<String>,
+	pub completion_index: usize,
+	//The loaded program /gel and /gml code runs in, picked with /use. None is the last one loaded.
+	pub program: Option<usize>,
+	//Where the history gets saved, None keeps it in memory only
+	pub history_file: Option<PathBuf>,
+	//The Ctrl+R search, while there is one
+	pub search: Option<HistorySearch>,
+	//The frame the main loop is on and when the console started, for stamping log entries
+	pub frame: i32,
+	pub started: Option<Instant>,
+	//How many lines back from the newest the log is scrolled
+	pub scroll: usize,
+	//What /filter is hiding
+	pub hidden_levels: Vec<LogLevel>,
+	pub hidden_sources: Vec<String>,
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum LogLevel {
+	Info,
+	Warning,
+	Error,
+}
+
+//One message in the console log
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct LogEntry {
+	pub text: String,
+	pub level: LogLevel,
+	//the file of the loaded program it came from, None for the console's own messages
+	pub source: Option<String>,
+	//the frame and time (since the console started) it was logged at, or last repeated at
+	pub frame: i32,
+	pub time: Duration,
+	//how many more times the same message came in right after, so an error every frame is one line
+	pub repeats: u32,
+	//counts down while it fades out with the console closed
+	pub age: u32,
+}
+
+//A Ctrl+R search through the console history. The console text is what's being searched for.
+#[derive(Debug)]
+pub struct HistorySearch {
+	//the console text from before the search, for when it's cancelled
+	pub original: String,
+	//the text the matches were last found for
+	pub query: String,
+	//how many matches back from the newest it's on
+	pub skip: usize,
 }
 
 impl Program {
 	pub fn new() -> Program {
 		return Program{
 			functions: HashMap::new(), 
-			labels: vec![], 
 			sprites: HashMap::new(),
 			objects: vec![Object::new("Program".to_string(), 1)], 
 			objects_sorted: HashMap::from([("Program".to_string(), vec![1])]), 
@@ -211,15 +284,51 @@ impl Program {
 			..Default::default()
 		};
 	}
+	//Swaps in newly compiled code, keeping everything the old code made (objects, globals, sprites...)
+	pub fn reload_code(&mut self, program: Program) {
+		self.functions = program.functions;
+		self.gml_scoping = program.gml_scoping;
+		if let (Some(source), Some(new_source)) = (&mut self.source, program.source) {
+			source.files = new_source.files;
+		}
+	}
+	//The file it was loaded from, which is what /list shows and what its log messages are tagged with
+	pub fn name(&self) -> String {
+		return match &self.source {
+			Some(source) => source.files[0].0.to_owned(),
+			None => "(not from a file)".to_string(),
+		};
+	}
 	pub fn new_object(&mut self, object_type: String) -> usize {
 		self.id_index += 1;
 		let obj = Object::new(object_type.to_owned(), self.id_index);
 		self.objects.push(obj);
-		self.objects_sorted.insert(object_type, vec![self.id_index]);
+		self.objects_sorted.entry(object_type).or_insert(vec![]).push(self.id_index);
 		return self.id_index;
 	}
-	pub fn get_object(&mut self, id: usize) -> &mut Object {
-		return &mut self.objects[id];
+	//Objects are never removed, so an id is always one past its position (the Program itself is id 1)
+	pub fn objects(&self) -> &Vec<Object> {
+		return &self.objects;
+	}
+	pub fn get_object(&self, id: usize) -> Option<&Object> {
+		return self.objects.get(id.wrapping_sub(1));
+	}
+	pub fn get_object_mut(&mut self, id: usize) -> Option<&mut Object> {
+		return self.objects.get_mut(id.wrapping_sub(1));
+	}
+	//What a with statement runs over: a single instance for an Object, or every instance of a type for its name
+	pub fn instances_of(&self, target: &Data) -> Vec<usize> {
+		match target {
+			Data::Object(id) if self.get_object(*id).is_some() => {
+				return vec![*id];
+			}
+			Data::String(object_type) => {
+				return self.objects_sorted.get(object_type).cloned().unwrap_or(vec![]);
+			}
+			_ => {
+				return vec![];
+			}
+		}
 	}
 	pub fn enter_context(&mut self, object: usize) {
 		self.context.push(object);
@@ -227,14 +336,21 @@ impl Program {
 	pub fn exit_context(&mut self) {
 		self.context.pop();
 	}
-	pub fn get_self(&mut self) -> usize {
+	//How many contexts deep it is, for putting it back with leave_contexts
+	pub fn context_depth(&self) -> usize {
+		return self.context.len();
+	}
+	pub fn leave_contexts(&mut self, depth: usize) {
+		self.context.truncate(depth);
+	}
+	pub fn get_self(&self) -> usize {
 		let length = self.context.len();
 		if length > 0 {
 			return self.context[length - 1];
 		}
 		return 0;
 	}
-	pub fn get_other(&mut self) -> usize {
+	pub fn get_other(&self) -> usize {
 		let length = self.context.len();
 		if length > 1 {
 			return self.context[length - 2];
@@ -1,99 +1,107 @@
 use regex::Regex;
 use std::collections::HashMap;
-use crate::datatypes::GrammarToken;
+use crate::parse_table::GrammarToken;
+use crate::parse_table::Conflict;
+use crate::parse_table::ParseTable;
+use crate::parse_table::TokenAction;
 
 //A Converter from an input CFG to what the Top-Down Parser needs.
-//Predictive grammar is not needed (at least, hopefully eventually), as the generator handles that case.
-//TODO:
-//		Make check and fix for left recursion (similar to how it fixes non-predictive grammars)
-//		Make optional tokens/linked optional tokens (signified with (x) beforehand, with x being a tag)
-//			for example, Test::= (parens)LPAREN ID (parens)RPAREN (semicolon)SEMI
+//Predictive grammar is not needed, as the generator handles that case:
+//	Left recursion (Expr::= Expr PLUS Term | Term) is rewritten into a subrule (Expr::= Term Expr_2, Expr_2::= PLUS Term Expr_2 | NONE),
+//		so the parser sees Expr with the children Term PLUS Term PLUS Term...
+//	Rules that start with the same token are left-factored into a subrule
+//	Rules whose FIRST sets collide through nonterminals get the nonterminal at the front expanded in place until they can be left-factored
+//		(which means that nonterminal doesn't get its own node in the AST for those rules)
+//	Anything left that one token of lookahead can't decide comes back as an error listing the rule, its options and the tokens
+//Rules are written as Name::= option | option | ..., and the options can use:
+//	X? for an optional token, (A B) to group tokens, and (A B | C) for a choice inside a rule
+//	X* and X+ for zero-or-more and one-or-more, which turn into a repeating subrule (groups work too: (COMMA Expr)*)
+//	(x)X for linked optional tokens, every token tagged with x is either all there or all missing
+//		for example, Test::= (parens)LPAREN ID (parens)RPAREN (semicolon)SEMI
+//		the tag has to be right up against its token, otherwise it's read as a group
+//	Lines starting with // are comments
 //	There are two special values, being grammar rule Root and the the token type END.
 //	END is used for the end of the program, for if you want to end parsing early.
 //	Root is always the highest-level set of rules. (the lowest priority operators)
 //	Root is the only special value you need to use, the others are just there for simplification.
-pub fn grammar_generator(inputstr: String) -> HashMap<String, Vec<Vec<GrammarToken>>>{
+pub fn grammar_generator(inputstr: String) -> Result<ParseTable, String> {
 	let mut grammar: HashMap<String, Vec<Vec<GrammarToken>>> = HashMap::new();
 	//make a for loop to iterate through all parts of the cfg
+	let mut rules = vec![];
+	let inputstr: String = inputstr.lines().filter(|line| !line.trim_start().starts_with("//")).collect::<Vec<&str>>().join("\n");
 	for g in Regex::new(r"(?m)\s*(\w*)\s*::=\s*(.*)\s*$").unwrap().captures_iter(&inputstr) {
-		let mut rule_list = vec![];
+		//every rule gets a spot first, so the subrules made for * and + don't take a name that's used later on
+		grammar.insert(g.get(1).unwrap().as_str().to_string(), vec![]);
+		rules.push((g.get(1).unwrap().as_str().to_string(), g.get(2).unwrap().as_str().chars().collect::<Vec<char>>()));
+	}
+	for (name, body) in rules {
 		//make the list of options (split by | then parse each)
-		for r in Regex::new(r"(\s*\w*\s*)*(\||$)").unwrap().find_iter(g.get(2).unwrap().as_str()) {
-			let mut rule = vec![];
-			for tok in Regex::new(r"\s*(\w+)\s*").unwrap().captures_iter(r.as_str()) {
-				let token = tok.get(1).unwrap().as_str();
-				rule.push(GrammarToken{is_terminal: token.to_uppercase().eq(token), value: token.to_string(), lookahead: vec!["NONE".to_string()], is_subrule: false});
-			}
-			rule_list.push(rule);
+		let mut pos = 0;
+		let rule_list = parse_options(&body, &mut pos, &name, &mut grammar)?;
+		if pos < body.len() {
+			return Err(format!("Found an extra ')' in the grammar rule for {}", name));
 		}
 		//insert as new GrammarToken lists (grammar.insert();)
-		grammar.insert(g.get(1).unwrap().as_str().to_string(), rule_list);
+		grammar.insert(name, rule_list);
+	}
+	if !grammar.contains_key("Root") {
+		return Err("The grammar needs a Root rule to start from".to_string());
 	}
+	for name in sorted_names(&grammar) {
+		for option in &grammar[&name] {
+			for tok in option {
+				if !tok.is_terminal && !grammar.contains_key(&tok.value) {
+					return Err(format!("{} is used in the grammar rule for {}, but it's never defined", tok.value, name));
+				}
+			}
+		}
+	}
+
+	//FIRST sets would never finish on a left recursive rule, so those have to go first
+	remove_left_recursion(&mut grammar)?;
 	
-	let mut return_grammar: HashMap<String, Vec<Vec<GrammarToken>>>;
+	let mut rounds = 0;
 	loop {
-		//now find the look-ahead groups and fix issues with them
-		return_grammar = HashMap::new();
-		for g in &grammar {
-			let mut rule_list = vec![];
-			for r in g.1 {
-				let mut rule = vec![];
-				for tok in r {
-					let look_ahead = find_lookahead(tok, &grammar);
-					rule.push(GrammarToken{is_terminal: tok.is_terminal, value: tok.value.clone(), lookahead: look_ahead, is_subrule: tok.is_subrule});
-				}
-				rule_list.push(rule);
-			}
-			return_grammar.insert(g.0.to_string(), rule_list);
-		}
-		let mut rule_to_fix = (false, "", 0, 0);
-		'outer: for g in &return_grammar {
-			let mut i1 = 1;
-			for r in g.1 {
-				let mut i2 = 1;
-				for r2 in g.1 {
-					if i1 != i2 && r2[0].lookahead.iter().all(|item| item != "NONE" && r[0].lookahead.contains(item)) {
-						rule_to_fix = (true, g.0, i1 - 1, i2 - 1);
+		//now find the options whose FIRST sets overlap and fix issues with them
+		let first = first_sets(&grammar);
+		let mut rule_to_fix = (false, "".to_string(), 0, 0);
+		'outer: for name in sorted_names(&grammar) {
+			let options: Vec<Vec<String>> = grammar[&name].iter().map(|option| sequence_first(option, &first).0).collect();
+			for i1 in 0..options.len() {
+				for i2 in i1 + 1..options.len() {
+					if options[i2].iter().any(|item| options[i1].contains(item)) {
+						rule_to_fix = (true, name, i1, i2);
 						break 'outer;
-						//panic!("rules {} and {} of {} stop backtracking from working! Please fix now.", i1, i2, g.0);
 					}
-					i2+=1;
 				}
-				i1+=1;
 			}
 		}
 		//we found an issue! Time to fix it up
 		if rule_to_fix.0 {
+			rounds += 1;
+			if rounds > MAX_FIX_ROUNDS {
+				return Err(format!("This grammar is not predictive! Fixing up {} and its descendants never finished", rule_to_fix.1));
+			}
+			let name = rule_to_fix.1.as_str();
 			//add first element (which should be the same for each) to new subrule, move other elements to new rule as an "or"
-			//println!("{:?}, {:?}, {:?}", rule_to_fix.1, rule_to_fix.2, rule_to_fix.3);
-			//println!("{:?}", grammar[rule_to_fix.1]);
-			//println!("{:?}, {:?}", grammar[rule_to_fix.1][rule_to_fix.2], grammar[rule_to_fix.1][rule_to_fix.3]);
-			if grammar[rule_to_fix.1][rule_to_fix.2][0].value == grammar[rule_to_fix.1][rule_to_fix.3][0].value {
-				let mut subrule_num = 2;
-				let mut subrule_name = format!("{}_{}", rule_to_fix.1, subrule_num);
-				while grammar.contains_key(&subrule_name) {
-					subrule_num += 1;
-					subrule_name = format!("{}_{}", rule_to_fix.1, subrule_num);
-				}
-				let mut rule = grammar[rule_to_fix.1].to_owned();
-				rule.push(vec![rule[rule_to_fix.2][0].to_owned(), GrammarToken{is_terminal: false, value: subrule_name.to_string(), lookahead: vec!["NONE".to_string()], is_subrule: true}]);
+			if grammar[name][rule_to_fix.2][0].value == grammar[name][rule_to_fix.3][0].value {
+				let subrule_name = new_subrule_name(name, &grammar);
+				let mut rule = grammar[name].to_owned();
+				rule.push(vec![rule[rule_to_fix.2][0].to_owned(), subrule_token(&subrule_name)]);
 				rule[rule_to_fix.2].remove(0);
 				rule[rule_to_fix.3].remove(0);
 				let mut new_rule = vec![rule[rule_to_fix.2].to_owned(), rule[rule_to_fix.3].to_owned()];
 				if new_rule[0].len() == 0 {
-					new_rule[0].push(GrammarToken{is_terminal: true, value: "NONE".to_string(), lookahead: vec!["NONE".to_string()], is_subrule: false});
+					new_rule[0].push(none_token());
 				}
 				if new_rule[1].len() == 0 {
-					new_rule[1].push(GrammarToken{is_terminal: true, value: "NONE".to_string(), lookahead: vec!["NONE".to_string()], is_subrule: false});
+					new_rule[1].push(none_token());
 				}
 				grammar.insert(subrule_name.to_string(), new_rule);
-				rule.remove(rule_to_fix.2);
-				if rule_to_fix.2 <= rule_to_fix.3 {
-					rule_to_fix.3 -= 1;
-				}
+				//rule_to_fix.2 is always before rule_to_fix.3
 				rule.remove(rule_to_fix.3);
-				grammar.remove(&rule_to_fix.1.to_string());
-				grammar.insert(rule_to_fix.1.to_string(), rule);
+				rule.remove(rule_to_fix.2);
+				grammar.insert(name.to_string(), rule);
 			}else{
 				//if they aren't equal that means that there are nonterminals involved here, making things trickier.
 				//Problem: what to do with:
@@ -102,30 +110,614 @@ pub fn grammar_generator(inputstr: String) -> HashMap<String, Vec<Vec<GrammarTok
 				// C: a c
 				// D: a d
 				// (problem being that you have to go through B in code, but can't let it diverge from going to C)
-				//Possible Solution:
-				// A: A' C
-				// A': ([B,]C|D)[a] A'2 | (B)[b]
-				// A'2: ([B,]C)[c] | (D)[a d]
-				// B: C | b
-				// C: a c
-				// D: a d
-				panic!("This grammar is not predictive! Take another look at {} and its descendants", rule_to_fix.1);
+				//Solution: expand the nonterminal at the front in place, one at a time, until the first tokens match up:
+				// A: C C | b C | D C
+				// A: a c C | b C | D C
+				// A: a c C | b C | a d C
+				//and then the left-factoring above can take it from there:
+				// A: b C | a A_2
+				// A_2: c C | d C
+				let index = if !grammar[name][rule_to_fix.2][0].is_terminal { rule_to_fix.2 } else { rule_to_fix.3 };
+				if grammar[name][index][0].is_terminal {
+					return Err(format!("This grammar is not predictive! Take another look at {} and its descendants", name));
+				}
+				let mut rule = grammar[name].to_owned();
+				let expanded = expand_front(&rule[index], &grammar);
+				rule.splice(index..index + 1, expanded);
+				grammar.insert(name.to_string(), rule);
 			}
 		} else {
 			break;
 		}
 	}
-	return return_grammar;
+	return build_table(grammar);
 }
 
-fn find_lookahead(token: &GrammarToken, grammar: &HashMap<String, Vec<Vec<GrammarToken>>>) -> Vec<String>{
+//Reads a token list for the scanner, one token per line as NAME ACTION regex (the regex is the rest of the line).
+//The longest match wins with ties going to the token higher up (see scanner), and lines starting with // are comments.
+pub fn token_list_generator(inputstr: &str) -> Result<Vec<(String, String, TokenAction)>, String> {
 	let mut ret_val = vec![];
-	if !token.is_terminal {
-		for rule in &grammar[&token.value] {
-			ret_val.append(&mut find_lookahead(&rule[0], &grammar));
+	for (index, line) in inputstr.lines().enumerate() {
+		let line = line.trim();
+		if line == "" || line.starts_with("//") {
+			continue;
+		}
+		let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+		let (action, pattern) = rest.trim_start().split_once(char::is_whitespace).unwrap_or((rest.trim_start(), ""));
+		let pattern = pattern.trim();
+		if pattern == "" {
+			return Err(format!("Line {} of the token list needs a name, an action and a regex", index + 1));
+		}
+		if !Regex::new(r"^[A-Za-z_]\w*$").unwrap().is_match(name) {
+			return Err(format!("Line {} of the token list has {} as a name, which can only use letters, numbers and _", index + 1, name));
+		}
+		let action = match action {
+			"Keywords" => TokenAction::Keywords,
+			"Identity" => TokenAction::Identity,
+			"Bool" => TokenAction::Bool,
+			"HexNum" => TokenAction::HexNum,
+			"BinNum" => TokenAction::BinNum,
+			"Number" => TokenAction::Number,
+			"String" => TokenAction::String,
+			"Newline" => TokenAction::Newline,
+			"Comment" => TokenAction::Comment,
+			"Whitespace" => TokenAction::Whitespace,
+			other => {
+				return Err(format!("Line {} of the token list has {} as an action, which isn't one of Keywords, Identity, Bool, HexNum, BinNum, Number, String, Newline, Comment or Whitespace", index + 1, other));
+			}
+		};
+		if ret_val.iter().any(|(other, _, _)| other == name) {
+			return Err(format!("Line {} of the token list uses the name {}, which is already used by another token", index + 1, name));
+		}
+		if let Err(error) = Regex::new(pattern) {
+			return Err(format!("Line {} of the token list has a broken regex for {}:\n{}", index + 1, name, error));
+		}
+		ret_val.push((name.to_string(), pattern.to_string(), action));
+	}
+	return Ok(ret_val);
+}
+
+//Rule names in order, so the generator makes the same subrules every time
+fn sorted_names(grammar: &HashMap<String, Vec<Vec<GrammarToken>>>) -> Vec<String> {
+	let mut names: Vec<String> = grammar.keys().cloned().collect();
+	names.sort();
+	return names;
+}
+
+//A piece of an option while it's being read: every list of tokens it could turn into, and its linked optional tag if it has one
+struct RuleItem {
+	tag: Option<String>,
+	options: Vec<Vec<GrammarToken>>,
+}
+
+//Reads options up to the end of the rule or the ) closing a group
+fn parse_options(body: &[char], pos: &mut usize, name: &str, grammar: &mut HashMap<String, Vec<Vec<GrammarToken>>>) -> Result<Vec<Vec<GrammarToken>>, String> {
+	let mut rule_list = vec![];
+	let mut items = vec![];
+	loop {
+		while *pos < body.len() && body[*pos].is_whitespace() {
+			*pos += 1;
+		}
+		if *pos >= body.len() || body[*pos] == ')' {
+			rule_list.append(&mut expand_items(items));
+			return Ok(rule_list);
+		}
+		if body[*pos] == '|' {
+			*pos += 1;
+			rule_list.append(&mut expand_items(items));
+			items = vec![];
+			continue;
+		}
+		let tag = parse_tag(body, pos);
+		let mut options;
+		if *pos < body.len() && body[*pos] == '(' {
+			*pos += 1;
+			options = parse_options(body, pos, name, grammar)?;
+			if *pos >= body.len() {
+				return Err(format!("Missing a ')' in the grammar rule for {}", name));
+			}
+			*pos += 1;
+		} else {
+			let start = *pos;
+			while *pos < body.len() && (body[*pos].is_alphanumeric() || body[*pos] == '_') {
+				*pos += 1;
+			}
+			if start == *pos {
+				return Err(format!("Unexpected '{}' in the grammar rule for {}", body.get(*pos).unwrap_or(&' '), name));
+			}
+			let token: String = body[start..*pos].iter().collect();
+			options = vec![vec![GrammarToken{is_terminal: token.to_uppercase().eq(&token), value: token, is_subrule: false}]];
+		}
+		match body.get(*pos) {
+			Some('?') => {
+				*pos += 1;
+				options.push(vec![]);
+			}
+			Some('*') => {
+				*pos += 1;
+				options = vec![vec![repeat_subrule(options, name, grammar)]];
+			}
+			Some('+') => {
+				*pos += 1;
+				let repeat = repeat_subrule(options.to_owned(), name, grammar);
+				for option in &mut options {
+					option.push(repeat.to_owned());
+				}
+			}
+			_ => {}
+		}
+		items.push(RuleItem{tag, options});
+	}
+}
+
+//Reads a linked optional tag like (parens) if there is one, which has to be right up against what it tags
+fn parse_tag(body: &[char], pos: &mut usize) -> Option<String> {
+	if body.get(*pos) != Some(&'(') {
+		return None;
+	}
+	let mut end = *pos + 1;
+	while end < body.len() && (body[end].is_alphanumeric() || body[end] == '_') {
+		end += 1;
+	}
+	if end == *pos + 1 || body.get(end) != Some(&')') {
+		return None;
+	}
+	match body.get(end + 1) {
+		Some(next) if next.is_alphanumeric() || *next == '_' || *next == '(' => {
+			let tag = body[*pos + 1..end].iter().collect();
+			*pos = end + 1;
+			return Some(tag);
+		}
+		_ => {
+			return None;
+		}
+	}
+}
+
+//Makes a subrule that matches the options any number of times, Name_2::= option Name_2 | NONE
+fn repeat_subrule(options: Vec<Vec<GrammarToken>>, name: &str, grammar: &mut HashMap<String, Vec<Vec<GrammarToken>>>) -> GrammarToken {
+	let subrule_name = new_subrule_name(name, grammar);
+	let mut rule = vec![];
+	for mut option in options {
+		if option.len() > 0 {
+			option.push(subrule_token(&subrule_name));
+			rule.push(option);
+		}
+	}
+	rule.push(vec![none_token()]);
+	grammar.insert(subrule_name.to_string(), rule);
+	return subrule_token(&subrule_name);
+}
+
+//Turns an option's items into every plain list of tokens it stands for.
+//Each linked optional tag is either on or off for the whole option, with the versions that have everything coming first.
+fn expand_items(items: Vec<RuleItem>) -> Vec<Vec<GrammarToken>> {
+	let mut tags: Vec<&String> = vec![];
+	for item in &items {
+		if let Some(tag) = &item.tag {
+			if !tags.contains(&tag) {
+				tags.push(tag);
+			}
+		}
+	}
+	let mut ret_val: Vec<Vec<GrammarToken>> = vec![];
+	for mask in (0..1usize << tags.len()).rev() {
+		let mut rules: Vec<Vec<GrammarToken>> = vec![vec![]];
+		for item in &items {
+			if let Some(tag) = &item.tag {
+				if mask & (1 << tags.iter().position(|t| *t == tag).unwrap()) == 0 {
+					continue;
+				}
+			}
+			let mut new_rules = vec![];
+			for rule in &rules {
+				for option in &item.options {
+					let mut new_rule = rule.to_owned();
+					new_rule.extend(option.iter().cloned());
+					new_rules.push(new_rule);
+				}
+			}
+			rules = new_rules;
+		}
+		for mut rule in rules {
+			if rule.len() == 0 {
+				rule.push(none_token());
+			}
+			//X? X? would give the same rule twice
+			if !ret_val.iter().any(|other| other.len() == rule.len() && other.iter().zip(&rule).all(|(a, b)| a.value == b.value)) {
+				ret_val.push(rule);
+			}
 		}
-	}else{
-		return vec![token.value.clone()];
 	}
 	return ret_val;
-}
\ No newline at end of file
+}
+
+//How many times the generator will rewrite rules before giving up on a grammar
+const MAX_FIX_ROUNDS: usize = 1000;
+
+fn none_token() -> GrammarToken {
+	return GrammarToken{is_terminal: true, value: "NONE".to_string(), is_subrule: false};
+}
+
+fn subrule_token(name: &str) -> GrammarToken {
+	return GrammarToken{is_terminal: false, value: name.to_string(), is_subrule: true};
+}
+
+fn new_subrule_name(rule: &str, grammar: &HashMap<String, Vec<Vec<GrammarToken>>>) -> String {
+	let mut subrule_num = 2;
+	let mut subrule_name = format!("{}_{}", rule, subrule_num);
+	while grammar.contains_key(&subrule_name) {
+		subrule_num += 1;
+		subrule_name = format!("{}_{}", rule, subrule_num);
+	}
+	return subrule_name;
+}
+
+//Replaces the nonterminal at the front of a rule with each of its own rules, keeping the rest of the rule after it
+//	A: B C, B: x y | NONE  =>  A: x y C | C
+fn expand_front(rule: &Vec<GrammarToken>, grammar: &HashMap<String, Vec<Vec<GrammarToken>>>) -> Vec<Vec<GrammarToken>> {
+	let mut ret_val = vec![];
+	for option in &grammar[&rule[0].value] {
+		let mut new_rule: Vec<GrammarToken> = option.iter().filter(|tok| tok.value != "NONE").cloned().collect();
+		new_rule.extend(rule[1..].iter().cloned());
+		if new_rule.len() == 0 {
+			new_rule.push(none_token());
+		}
+		ret_val.push(new_rule);
+	}
+	return ret_val;
+}
+
+//Whether rule can start with target (going through the first token of each rule on the way)
+fn left_derives(rule: &str, target: &str, grammar: &HashMap<String, Vec<Vec<GrammarToken>>>, visited: &mut Vec<String>) -> bool {
+	if visited.iter().any(|name| name == rule) || !grammar.contains_key(rule) {
+		return false;
+	}
+	visited.push(rule.to_string());
+	for option in &grammar[rule] {
+		let first = &option[0];
+		if first.is_terminal {
+			continue;
+		}
+		if first.value == target || left_derives(&first.value, target, grammar, visited) {
+			return true;
+		}
+	}
+	return false;
+}
+
+//The textbook left recursion removal:
+//	Indirect recursion (A: B x, B: A y | z) gets B expanded into A first (A: A y x | z x)
+//	Then the direct recursion A: A a | b turns into A: b A_2, A_2: a A_2 | NONE, where A_2 is a subrule
+//		so the AST gets A with the children b a a a..., which reads left to right just like the original rule
+//This only looks at the first token of each rule, a left recursion hiding behind a rule that can be NONE isn't caught
+fn remove_left_recursion(grammar: &mut HashMap<String, Vec<Vec<GrammarToken>>>) -> Result<(), String> {
+	for name in sorted_names(grammar) {
+		let mut rounds = 0;
+		loop {
+			let index = grammar[&name].iter().position(|option| !option[0].is_terminal && option[0].value != name && left_derives(&option[0].value, &name, grammar, &mut vec![]));
+			match index {
+				Some(index) => {
+					rounds += 1;
+					if rounds > MAX_FIX_ROUNDS {
+						return Err(format!("This grammar's left recursion couldn't be removed! Take another look at {} and its descendants", name));
+					}
+					let mut rule = grammar[&name].to_owned();
+					let expanded = expand_front(&rule[index], grammar);
+					rule.splice(index..index + 1, expanded);
+					grammar.insert(name.to_string(), rule);
+				}
+				None => {
+					break;
+				}
+			}
+		}
+
+		let (recursive, others): (Vec<Vec<GrammarToken>>, Vec<Vec<GrammarToken>>) = grammar[&name].iter().cloned().partition(|option| option[0].value == name);
+		if recursive.len() == 0 {
+			continue;
+		}
+		if others.len() == 0 {
+			return Err(format!("{} only has left recursive rules, so it can never finish", name));
+		}
+		let subrule_name = new_subrule_name(&name, grammar);
+		let mut rule = vec![];
+		for option in others {
+			let mut new_rule: Vec<GrammarToken> = option.into_iter().filter(|tok| tok.value != "NONE").collect();
+			new_rule.push(subrule_token(&subrule_name));
+			rule.push(new_rule);
+		}
+		let mut subrule = vec![];
+		for option in recursive {
+			//A: A on its own doesn't add anything
+			if option.len() > 1 {
+				let mut new_rule = option[1..].to_vec();
+				new_rule.push(subrule_token(&subrule_name));
+				subrule.push(new_rule);
+			}
+		}
+		subrule.push(vec![none_token()]);
+		grammar.insert(name.to_string(), rule);
+		grammar.insert(subrule_name, subrule);
+	}
+	return Ok(());
+}
+
+//FIRST of every rule (the tokens it can start with), and whether it can match nothing at all
+fn first_sets(grammar: &HashMap<String, Vec<Vec<GrammarToken>>>) -> HashMap<String, (Vec<String>, bool)> {
+	let mut first: HashMap<String, (Vec<String>, bool)> = grammar.keys().map(|name| (name.to_string(), (vec![], false))).collect();
+	let mut changed = true;
+	while changed {
+		changed = false;
+		for (name, options) in grammar {
+			for option in options {
+				let (tokens, nullable) = sequence_first(option, &first);
+				let entry = first.get_mut(name).unwrap();
+				for token in tokens {
+					if !entry.0.contains(&token) {
+						entry.0.push(token);
+						changed = true;
+					}
+				}
+				if nullable && !entry.1 {
+					entry.1 = true;
+					changed = true;
+				}
+			}
+		}
+	}
+	return first;
+}
+
+//FIRST of a list of tokens, and whether the whole list can match nothing
+fn sequence_first(option: &[GrammarToken], first: &HashMap<String, (Vec<String>, bool)>) -> (Vec<String>, bool) {
+	let mut ret_val: Vec<String> = vec![];
+	for tok in option {
+		if tok.value == "NONE" {
+			continue;
+		}
+		if tok.is_terminal {
+			if !ret_val.contains(&tok.value) {
+				ret_val.push(tok.value.clone());
+			}
+			return (ret_val, false);
+		}
+		let (tokens, nullable) = &first[&tok.value];
+		for token in tokens {
+			if !ret_val.contains(token) {
+				ret_val.push(token.clone());
+			}
+		}
+		if !nullable {
+			return (ret_val, false);
+		}
+	}
+	return (ret_val, true);
+}
+
+//FOLLOW of every rule: the tokens that can come right after it (END comes after Root)
+fn follow_sets(grammar: &HashMap<String, Vec<Vec<GrammarToken>>>, first: &HashMap<String, (Vec<String>, bool)>) -> HashMap<String, Vec<String>> {
+	let mut follow: HashMap<String, Vec<String>> = grammar.keys().map(|name| (name.to_string(), vec![])).collect();
+	follow.get_mut("Root").unwrap().push("END".to_string());
+	let mut changed = true;
+	while changed {
+		changed = false;
+		for (name, options) in grammar {
+			for option in options {
+				for (index, tok) in option.iter().enumerate() {
+					if tok.is_terminal {
+						continue;
+					}
+					let (mut tokens, nullable) = sequence_first(&option[index + 1..], first);
+					if nullable {
+						tokens.extend(follow[name].iter().cloned());
+					}
+					let entry = follow.get_mut(&tok.value).unwrap();
+					for token in tokens {
+						if !entry.contains(&token) {
+							entry.push(token);
+							changed = true;
+						}
+					}
+				}
+			}
+		}
+	}
+	return follow;
+}
+
+//Fills in the parse table. When more than one option fits a token, the one that starts with that token wins
+//(the others only fit because they can match nothing and the token can come after the rule, like an else that could end an if early),
+//and if that doesn't settle it then the grammar is ambiguous there and it gets reported.
+//The settled ones are kept in the table's resolved list, and listed with the errors so it's clear what was already picked.
+fn build_table(rules: HashMap<String, Vec<Vec<GrammarToken>>>) -> Result<ParseTable, String> {
+	let first = first_sets(&rules);
+	let follow = follow_sets(&rules, &first);
+	let mut table = HashMap::new();
+	let mut resolved = vec![];
+	let mut conflicts = vec![];
+	for name in sorted_names(&rules) {
+		//token -> (every option that fits it, the options that start with it)
+		let mut cells: HashMap<String, (Vec<usize>, Vec<usize>)> = HashMap::new();
+		for (index, option) in rules[&name].iter().enumerate() {
+			let (tokens, nullable) = sequence_first(option, &first);
+			for token in tokens {
+				let cell = cells.entry(token).or_insert((vec![], vec![]));
+				cell.0.push(index);
+				cell.1.push(index);
+			}
+			if nullable {
+				for token in &follow[&name] {
+					let cell = cells.entry(token.to_string()).or_insert((vec![], vec![]));
+					if !cell.0.contains(&index) {
+						cell.0.push(index);
+					}
+				}
+			}
+		}
+		let mut tokens: Vec<&String> = cells.keys().collect();
+		tokens.sort();
+		let mut row = HashMap::new();
+		for token in tokens {
+			let (options, starts) = &cells[token];
+			if options.len() == 1 {
+				row.insert(token.to_string(), options[0]);
+			} else if starts.len() == 1 {
+				row.insert(token.to_string(), starts[0]);
+				add_conflict(&mut resolved, &name, options, token);
+			} else {
+				add_conflict(&mut conflicts, &name, options, token);
+			}
+		}
+		table.insert(name, row);
+	}
+	if conflicts.len() > 0 {
+		return Err(conflict_report(&rules, &conflicts, &resolved));
+	}
+	return Ok(ParseTable{rules, table, resolved});
+}
+
+//Conflicts between the same options get their tokens listed together
+fn add_conflict(conflicts: &mut Vec<Conflict>, rule: &str, options: &Vec<usize>, token: &str) {
+	match conflicts.iter_mut().find(|conflict| conflict.rule == rule && conflict.options == *options) {
+		Some(conflict) => {
+			conflict.tokens.push(token.to_string());
+		}
+		None => {
+			conflicts.push(Conflict{rule: rule.to_string(), options: options.to_owned(), tokens: vec![token.to_string()]});
+		}
+	}
+}
+
+pub fn conflict_report(rules: &HashMap<String, Vec<Vec<GrammarToken>>>, conflicts: &[Conflict], resolved: &[Conflict]) -> String {
+	let mut ret_val = "This grammar can't always tell which option to take from the next token:".to_string();
+	push_conflicts(&mut ret_val, rules, conflicts);
+	if resolved.len() > 0 {
+		ret_val.push_str("\nThese were settled by taking the option that starts with the token:");
+		push_conflicts(&mut ret_val, rules, resolved);
+	}
+	return ret_val;
+}
+
+fn push_conflicts(ret_val: &mut String, rules: &HashMap<String, Vec<Vec<GrammarToken>>>, conflicts: &[Conflict]) {
+	for conflict in conflicts {
+		ret_val.push_str(&format!("\n\t{} on {}:", conflict.rule, conflict.tokens.join(", ")));
+		for option in &conflict.options {
+			let tokens: Vec<&str> = rules[&conflict.rule][*option].iter().map(|tok| tok.value.as_str()).collect();
+			ret_val.push_str(&format!("\n\t\toption {}: {}", option + 1, tokens.join(" ")));
+		}
+	}
+}
+
+#[cfg(test)]
+fn test_tokens(types: &[&str]) -> Vec<(String, String, i32)> {
+	return types.iter().map(|typ| (typ.to_string(), typ.to_lowercase(), 1)).collect();
+}
+
+#[test]
+fn left_recursion_test(){
+	let grammar = grammar_generator(String::from("
+		Root::= Expr
+		Expr::= Expr PLUS Term | Term
+		Term::= Term MULT Val | Val
+		Val::= INT | LPAREN Expr RPAREN
+	")).unwrap();
+	for (name, rules) in grammar.rules.iter() {
+		assert!(rules.iter().all(|rule| rule[0].value != *name));
+	}
+	let ast = crate::parser::parser::parser(test_tokens(&["INT", "PLUS", "INT", "MULT", "INT", "PLUS", "LPAREN", "INT", "RPAREN"]), &grammar).unwrap();
+	let expr = &ast.children[0];
+	//the subrules leave a NONE behind where they stop
+	let rules: Vec<&str> = expr.children.iter().map(|node| node.rule.as_str()).filter(|rule| *rule != "NONE").collect();
+	assert!(rules == vec!["Term", "PLUS", "Term", "PLUS", "Term"]);
+	let rules: Vec<&str> = expr.children[2].children.iter().map(|node| node.rule.as_str()).filter(|rule| *rule != "NONE").collect();
+	assert!(rules == vec!["Val", "MULT", "Val"]);
+}
+
+#[test]
+fn nonterminal_factoring_test(){
+	//the example from grammar_generator, which used to panic (Root ends in NONE like GEL's Block, since the parser needs that to finish)
+	let grammar = grammar_generator(String::from("
+		Root::= Start Root | NONE
+		Start::= Bee Sea | Dee Sea
+		Bee::= Sea | B
+		Sea::= A C
+		Dee::= A D
+	")).unwrap();
+	for tokens in [vec!["A", "C", "A", "C"], vec!["A", "D", "A", "C"], vec!["B", "A", "C"]] {
+		assert!(crate::parser::parser::parser(test_tokens(&tokens), &grammar).is_ok());
+	}
+	assert!(crate::parser::parser::parser(test_tokens(&["A", "D", "A", "D"]), &grammar).is_err());
+}
+
+#[test]
+fn optional_tokens_test(){
+	let grammar = grammar_generator(String::from("
+		Root::= Stat Root | NONE
+		Stat::= (parens)LPAREN ID (parens)RPAREN SEMI? | List | COLON INT+
+		List::= LBRACE (ID (COMMA ID)*)? RBRACE
+	")).unwrap();
+	for tokens in [vec!["LPAREN", "ID", "RPAREN", "SEMI"], vec!["ID", "ID", "SEMI"], vec!["LBRACE", "RBRACE"], vec!["COLON", "INT", "INT", "INT"]] {
+		assert!(crate::parser::parser::parser(test_tokens(&tokens), &grammar).is_ok());
+	}
+	for tokens in [vec!["LPAREN", "ID", "SEMI"], vec!["ID", "RPAREN"], vec!["LBRACE", "ID", "COMMA", "RBRACE"], vec!["COLON"]] {
+		assert!(crate::parser::parser::parser(test_tokens(&tokens), &grammar).is_err());
+	}
+	//the repeats are subrules, so the list comes out flat
+	let ast = crate::parser::parser::parser(test_tokens(&["LBRACE", "ID", "COMMA", "ID", "COMMA", "ID", "RBRACE"]), &grammar).unwrap();
+	let list = &ast.children[0].children[0];
+	let rules: Vec<&str> = list.children.iter().map(|node| node.rule.as_str()).filter(|rule| *rule != "NONE").collect();
+	assert!(rules == vec!["LBRACE", "ID", "COMMA", "ID", "COMMA", "ID", "RBRACE"]);
+}
+
+#[test]
+fn conflict_report_test(){
+	//both options can match nothing, so nothing can decide between them at the end of the program
+	let error = grammar_generator(String::from("
+		Root::= Thing
+		Thing::= One | Two
+		One::= ID | NONE
+		Two::= INT | NONE
+	")).err().unwrap();
+	assert!(error.contains("Thing on END:\n\t\toption 1: One\n\t\toption 2: Two"));
+	assert!(grammar_generator(String::from("Root::= Missing")).err().unwrap().contains("Missing is used in the grammar rule for Root"));
+
+	//a dangling else goes to the closest if, and that gets noted instead of failing
+	let grammar = grammar_generator(String::from("
+		Root::= Stat Root | NONE
+		Stat::= IF ID Stat Else | ID
+		Else::= ELSE Stat | NONE
+	")).unwrap();
+	assert!(grammar.table["Else"]["ELSE"] == 0);
+	assert!(grammar.resolved == vec![Conflict{rule: "Else".to_string(), options: vec![0, 1], tokens: vec!["ELSE".to_string()]}]);
+	let ast = crate::parser::parser::parser(test_tokens(&["IF", "ID", "IF", "ID", "ID", "ELSE", "ID"]), &grammar).unwrap();
+	let outer_else = &ast.children[0].children[3];
+	assert!(outer_else.children[0].rule == "NONE");
+
+	//and when the grammar fails anyway, the settled ones are listed after the errors
+	let error = grammar_generator(String::from("
+		Root::= Stat Root | NONE
+		Stat::= IF ID Stat Else | Thing
+		Else::= ELSE Stat | NONE
+		Thing::= One | Two
+		One::= ID | NONE
+		Two::= INT | NONE
+	")).err().unwrap();
+	assert!(error.contains("settled by taking the option that starts with the token:\n\tElse on ELSE:\n\t\toption 1: ELSE Stat\n\t\toption 2: NONE"));
+}
+
+#[test]
+fn token_list_test(){
+	let tokens = token_list_generator("
+		//comments and blank lines are skipped
+		ARROW    Identity    ->
+		ID       Keywords    [a-zA-Z_]\\w* 
+	").unwrap();
+	assert!(tokens.len() == 2);
+	assert!(tokens[0].0 == "ARROW" && tokens[0].1 == "->");
+	assert!(tokens[1].1 == "[a-zA-Z_]\\w*" && matches!(tokens[1].2, TokenAction::Keywords));
+	assert!(token_list_generator("ID Keywords").err().unwrap().contains("Line 1"));
+	assert!(token_list_generator("ID Shout [a-z]+").err().unwrap().contains("Shout"));
+	assert!(token_list_generator("ID Identity [a-z\n").err().unwrap().contains("broken regex"));
+	assert!(token_list_generator("ID Identity a\nID Identity b").err().unwrap().contains("already used"));
+}
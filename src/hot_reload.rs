@@ -0,0 +1,95 @@
+use std::fs;
+use crate::datatypes::{Program, Source};
+use crate::parser::parser_setup::{load_dialect, modified, try_compile_file, try_compile_file_with};
+
+//How many frames go by between checking loaded programs' files for changes
+pub const WATCH_INTERVAL: i32 = 30;
+
+//Compiles a loaded program's file again and swaps the new functions in, keeping its objects, globals and sprites.
+//If the new code doesn't compile, the old code keeps running.
+pub fn reload_program(program: &mut Program) -> Result<String, String> {
+	let source = match &mut program.source {
+		Some(source) => source,
+		None => {
+			return Err("That program wasn't loaded from a file, so there's nothing to reload".to_string());
+		}
+	};
+	//it's been looked at either way, so a broken save isn't tried again every time the files get checked
+	for file in &mut source.files {
+		file.1 = modified(&file.0);
+	}
+	let filename = source.files[0].0.to_owned();
+	match compile_again(source) {
+		Ok(new_program) => {
+			//if it didn't compile when it was loaded, its top level code and init haven't really run yet
+			let never_compiled = program.functions.is_empty();
+			program.reload_code(new_program);
+			if never_compiled {
+				program.initialized = false;
+			}
+			return Ok(format!("Reloaded {}", filename));
+		}
+		Err(error) => {
+			return Err(format!("{} didn't compile, so the old code is still running\n{}", filename, error));
+		}
+	}
+}
+
+//Compiles a loaded program from scratch, throwing away everything it made, so it runs its top level code and init again.
+//If it doesn't compile, the old program keeps running as it was.
+pub fn restart_program(program: &mut Program) -> Result<String, String> {
+	let source = match &program.source {
+		Some(source) => source,
+		None => {
+			return Err("That program wasn't loaded from a file, so there's nothing to restart".to_string());
+		}
+	};
+	let filename = source.files[0].0.to_owned();
+	match compile_again(source) {
+		Ok(mut new_program) => {
+			if let Some(new_source) = &mut new_program.source {
+				new_source.dialect = source.dialect.clone();
+			}
+			*program = new_program;
+			return Ok(format!("Restarted {}", filename));
+		}
+		Err(error) => {
+			return Err(format!("{} didn't compile, so the old program is still running\n{}", filename, error));
+		}
+	}
+}
+
+//Compiles the file a program was loaded from, with the dialect it was loaded with
+fn compile_again(source: &Source) -> Result<Program, String> {
+	let filename = &source.files[0].0;
+	return match &source.dialect {
+		Some(dialect) => load_dialect(dialect).and_then(|dialect| try_compile_file_with(filename, &dialect)),
+		None => try_compile_file(filename),
+	};
+}
+
+//Reloads the program if any of the files it was compiled from have changed since
+pub fn reload_if_changed(program: &mut Program) -> Option<Result<String, String>> {
+	let changed = match &program.source {
+		Some(source) => source.files.iter().any(|(file, time)| modified(file) != *time),
+		None => false,
+	};
+	if !changed {
+		return None;
+	}
+	return Some(reload_program(program));
+}
+
+//Whether filename is the file the program was loaded from
+pub fn loaded_from(program: &Program, filename: &str) -> bool {
+	let loaded = match &program.source {
+		Some(source) => &source.files[0].0,
+		None => {
+			return false;
+		}
+	};
+	return match (fs::canonicalize(loaded), fs::canonicalize(filename)) {
+		(Ok(loaded), Ok(filename)) => loaded == filename,
+		_ => false,
+	};
+}
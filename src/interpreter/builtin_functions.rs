@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use crate::{datatypes::{Data, Program, SpriteData}, interpreter::unwrap_values::get_value};
+use crate::{datatypes::{Data, Program, ScriptCommand, SpriteData}, interpreter::{unwrap_values::get_value, interpreter::interpret_function}};
 use macroquad::prelude::{Color, camera::mouse};
 use regex::Regex;
 use rust_decimal::prelude::*;
@@ -27,8 +27,27 @@ const FUNCTION_LIST: &'static [Function] = &[
     Function{name: "add_sprite", desc: "Adds a new sprite from the path {path} and returns a Sprite", args: &[FunctionArg{name: "path", typ: "path (string)"}]},
     Function{name: "add_sprite", desc: "Adds a new sprite from the path {path} and returns a Sprite named {name}", args: &[FunctionArg{name: "path", typ: "path (string)"}, FunctionArg{name: "sprite name", typ: "string"}]},
     Function{name: "draw_sprite", desc: "Draws the sprite named {sprite name} to the screen at {x}, {y}, blended with color {color}", args: &[FunctionArg{name: "sprite name", typ: "sprite name (string)"}, FunctionArg{name: "x", typ: "number"}, FunctionArg{name: "y", typ: "number"}, FunctionArg{name: "color", typ: "color"}]},
+    Function{name: "draw_sprite", desc: "Draws the sprite named {sprite name} to the screen at {x}, {y}, GameMaker style (sprites only have one image for now, so {subimg} is ignored)", args: &[FunctionArg{name: "sprite name", typ: "sprite name (string)"}, FunctionArg{name: "subimg", typ: "number"}, FunctionArg{name: "x", typ: "number"}, FunctionArg{name: "y", typ: "number"}]},
+    Function{name: "instance_create", desc: "Creates an Object of type {type} with its x and y set to {x}, {y}, then runs the function {type}_create as it if there is one", args: &[FunctionArg{name: "x", typ: "number"}, FunctionArg{name: "y", typ: "number"}, FunctionArg{name: "type", typ: "object type (string)"}]},
+    Function{name: "console_command", desc: "Adds the console command /{name}, which calls the function {function} with the rest of the line as a string", args: &[FunctionArg{name: "name", typ: "string"}, FunctionArg{name: "function", typ: "function name (string)"}]},
+    Function{name: "console_command", desc: "Adds the console command /{name}, which calls the function {function} with the rest of the line as a string, and shows {usage} in /help", args: &[FunctionArg{name: "name", typ: "string"}, FunctionArg{name: "function", typ: "function name (string)"}, FunctionArg{name: "usage", typ: "string"}]},
 ];
 
+//Every builtin's name, once each even when it has more than one version
+pub fn builtin_names() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = FUNCTION_LIST.iter().map(|func| func.name).collect();
+    names.dedup();
+    return names;
+}
+
+//How to call a builtin, like draw_text(text: string, x: number, ...), one line for each version of it
+pub fn builtin_signatures(name: &str) -> Vec<String> {
+    return FUNCTION_LIST.iter().filter(|func| func.name == name).map(|func| {
+        let args: Vec<String> = func.args.iter().map(|arg| format!("{}: {}", arg.name, arg.typ)).collect();
+        return format!("{}({})", func.name, args.join(", "));
+    }).collect();
+}
+
 pub fn builtin_functions() -> HashMap<String, (Data, Data)> {
     let mut funcs = HashMap::new();
     for func in FUNCTION_LIST {
@@ -43,7 +62,7 @@ pub fn run_builtin(name: &str, args: Vec<Data>, registers: &HashMap<u32, Data>,
         "print" | "trace" => {
             for arg in args {
                 //println!("{}", get_value(&arg, &registers, &variables)?.to_string());
-                program.log.push(get_value(&arg, &registers, &variables)?.to_string());
+                program.log.push(get_value(&arg, &registers, &variables, program)?.to_string());
             }
             return Ok(Some(Data::Null));
         }
@@ -81,6 +100,23 @@ pub fn run_builtin(name: &str, args: Vec<Data>, registers: &HashMap<u32, Data>,
                 return Ok(Some(Data::Null));
             }
         }
+        "instance_create" => {
+            if let [Data::Decimal(x), Data::Decimal(y), Data::String(object_type)] = &args[..] {
+                let id = program.new_object(object_type.to_owned());
+                let object = program.get_object_mut(id).unwrap();
+                object.data.insert("x".to_string(), Data::Decimal(*x));
+                object.data.insert("y".to_string(), Data::Decimal(*y));
+                //NTT style creation functions, which run as the new instance with the creator as other
+                program.enter_context(id);
+                let created = interpret_function(program, &format!("{}_create", object_type), vec![Data::Decimal(*x), Data::Decimal(*y)]);
+                program.exit_context();
+                created?;
+                return Ok(Some(Data::Object(id)));
+            } else {
+                builtin_error(name, args);
+                return Ok(Some(Data::Null));
+            }
+        }
         //TODO: make sprite loading nonsync?
         "add_sprite" => {
             if let [Data::String(path), Data::String(name)] = &args[..] {
@@ -94,11 +130,28 @@ pub fn run_builtin(name: &str, args: Vec<Data>, registers: &HashMap<u32, Data>,
             if let [Data::String(spr_name), Data::Decimal(x), Data::Decimal(y), Data::Color(r,g,b,a)] = &args[..] {
                 draw_texture(&program.sprites[spr_name].texture, x.to_f32().unwrap(), y.to_f32().unwrap(), Color::new(r.to_f32().unwrap(), g.to_f32().unwrap(), b.to_f32().unwrap(), a.to_f32().unwrap()));
                 return Ok(Some(Data::Null));
+            } else if let [Data::String(spr_name), Data::Decimal(_subimg), Data::Decimal(x), Data::Decimal(y)] = &args[..] {
+                draw_texture(&program.sprites[spr_name].texture, x.to_f32().unwrap(), y.to_f32().unwrap(), WHITE);
+                return Ok(Some(Data::Null));
             } else {
                 builtin_error(name, args);
                 return Ok(Some(Data::Null));
             }
         }
+        "console_command" => {
+            let (command_name, function, usage) = match &args[..] {
+                [Data::String(command_name), Data::String(function)] => (command_name, function, String::new()),
+                [Data::String(command_name), Data::String(function), Data::String(usage)] => (command_name, function, usage.to_owned()),
+                _ => {
+                    return Err("console_command takes (name, function[, usage]) strings".to_string());
+                }
+            };
+            let command_name = command_name.trim_start_matches('/').to_string();
+            //running init again replaces the command instead of adding it twice
+            program.commands.retain(|command| command.name != command_name);
+            program.commands.push(ScriptCommand{name: command_name, function: function.to_owned(), usage});
+            return Ok(Some(Data::Null));
+        }
         "mouse_position_x" => {
             let mouse_pos = mouse_position();
             return Ok(Some(Data::Decimal(Decimal::from_f32(mouse_pos.0).unwrap())));
@@ -1,5 +1,6 @@
 
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::vec;
 use crate::interpreter::unwrap_values::*;
 use crate::interpreter::operators::data_operation;
@@ -11,179 +12,215 @@ use macroquad::prelude::*;
 use rust_decimal::prelude::*;
 
 pub fn interpret_program(program: &mut Program, startingfunction: &str) -> Result<Data, String>{
-	let opcodes;
-	{
-		let function = program.functions.get(startingfunction);
-		if function.is_none() {
-			return Ok(Data::Null);
-		}
-		opcodes = function.unwrap().1.to_owned();
-	}
+	return interpret_function(program, startingfunction, vec![]);
+}
+
+//Runs a function with the values it was called with, which it gets as the locals argument0, argument1... and argument_count
+pub fn interpret_function(program: &mut Program, startingfunction: &str, args: Vec<Data>) -> Result<Data, String>{
+	//Functions are shared through an Rc so calling one doesn't copy its opcodes
+	let function = match program.functions.get(startingfunction) {
+		Some(function) => Rc::clone(function),
+		None => return Ok(Data::Null),
+	};
+	//leaving in the middle of a with statement, by returning or by an error, still has to put self back
+	let depth = program.context_depth();
+	let result = run_function(program, &function, args);
+	program.leave_contexts(depth);
+	return result;
+}
+
+fn run_function(program: &mut Program, function: &Function, args: Vec<Data>) -> Result<Data, String>{
+	let opcodes = &function.opcodes;
 	
 	let mut registers: HashMap<u32, Data> = HashMap::new();
-	//let mut labels: HashMap<Data, u32> = HashMap::new();
 	let mut variables: HashMap<String, (Data, Data)> = builtin_variables();
 	variables.extend(builtin_functions());
-	//let mut functions: HashMap<Data, (u32, Vec<(Data, Data)>)> = HashMap::new();
-	let mut jump_point;
+	if program.gml_scoping {
+		variables.insert("argument_count".to_string(), (Data::Type("int".to_string()), Data::Decimal(Decimal::from(args.len()))));
+		for (index, arg) in args.iter().enumerate() {
+			variables.insert(format!("argument{}", index), (Data::Type("var".to_string()), arg.clone()));
+		}
+	}
+	//Named parameters take the arguments in order, as their ARG opcodes come up
+	let mut next_arg = 0;
 	let mut func_stack: Vec<u32> = vec![];
-	let mut _position = 1;
-	/*for op in opcodes {
+	//The instances each with statement still has to go through, and which one it's on
+	let mut with_stack: Vec<(Vec<usize>, usize)> = vec![];
+	//Index of the next opcode to run, jumps just overwrite it
+	let mut pc = 0;
+	while pc < opcodes.len() {
+		let op = &opcodes[pc];
+		pc += 1;
 		match op.instruction.as_str() {
-			"FOR_LABEL" | "IF_LABEL" | "ELSE_LABEL" => {
-				labels.insert(op.data.clone(), position);
+			"NOP" => {}
+			"Declare" => {
+				if let Data::Variable(data2) = &op.data2 {
+					variables.insert(data2.to_owned(), (op.data.clone(), Data::Null));
+				}
 			}
-			/*"FUNC_DEF" => {
-				functions.insert(op.data.clone(), (position, unwrap_function_definition(position, &opcodes)));
-			}*/
-			_ => {}
-		}
-		position += 1;
-	}*/
-	/*let startfn = Data::Variable(startingfunction.to_string());
-	if functions.contains_key(&startfn) {
-		jump_point = functions[&startfn].0;
-	}else if startingfunction == "init" {
-		jump_point = 1;
-	} else {
-		//println!("{} is not a valid function", startingfunction);
-		return;
-	}*/
-	jump_point = 1;
-	while jump_point != 0 {
-		let iter = opcodes.iter().skip(jump_point.to_usize().unwrap()-1);
-		_position = jump_point;
-		jump_point = 0;
-		for op in iter {
-			//println!("{:?}", op);
-			//println!("{:?}", position);
-			match op.instruction.as_str() {
-				"NOP" => {}
-				"Declare" => {
-					if let Data::Variable(data2) = &op.data2 {
-						variables.insert(data2.to_owned(), (op.data.clone(), Data::Null));
+			"Set" => {
+				//Stat2 points Set at the ID's register, which holds the variable
+				let mut target = op.data.clone();
+				if let Data::Register(reg) = target {
+					target = registers.get(&reg).cloned().unwrap_or(Data::Null);
+				}
+				if let Data::Variable(data) = &target {
+					if let Data::Register(data2) = op.data2 {
+						if registers.contains_key(&data2) {
+							let value = get_value(&op.data2, &registers, &variables, program)?;
+							set_variable(data, value, &registers, &mut variables, program)?;
+						}
 					}
 				}
-				"Set" => {
-					if let Data::Variable(data) = &op.data {
-						if variables.contains_key(data) {
-							if let Data::Register(data2) = op.data2 {
-								if registers.contains_key(&data2) {
-									variables.get_mut(&data.to_owned()).unwrap().1 = registers.get_mut(&data2).unwrap().clone();
-								}
-							}
-						}//else if(program.){
-							//object/self variable check
-						//}
+			}
+			"Value" => {
+				registers.insert(op.register, op.data.clone());
+			}
+			"ID" => {
+				registers.insert(op.register, op.data.clone());
+			}
+			"Comma" => {
+				registers.insert(op.register, Data::Comma(Box::new(op.data.clone()), Box::new(op.data2.clone())));
+			}
+			"FUNC" => {
+				if let Data::Function(func, _args) = get_value(&op.data, &registers, &variables, program)?{
+					let args = unwrap_function_inputs(&op.data2, &registers, &variables, program)?;
+					let data = run_builtin(func.as_str(), args.clone(), &registers, &variables, program)?;
+					if data.is_none() {
+						if program.functions.contains_key(&func) {
+							registers.insert(op.register, interpret_function(program, &func, args)?);
+						}else{
+							panic!("ERROR: FUNCTION {} DOES NOT EXIST ON LINE {}", func, op.line);
+						}
+					}else{
+						registers.insert(op.register, data.unwrap());
 					}
+				} else {
+					panic!("ERROR: {} IS NOT A DATATYPE THAT CAN BE A FUNCTION ON LINE {}", op.data, op.line);
 				}
-				"Value" => {
-					registers.insert(op.register, op.data.clone());
+			}
+			"ARG" => {
+				//the default is only worked out when the call didn't pass enough arguments
+				if let Data::Variable(name) = &op.data {
+					let value = match args.get(next_arg) {
+						Some(arg) => arg.clone(),
+						None => get_value(&op.data2, &registers, &variables, program)?,
+					};
+					let typ = variables.get(name).map_or(Data::Type("var".to_string()), |(typ, _)| typ.clone());
+					variables.insert(name.to_owned(), (typ, value));
+					next_arg += 1;
 				}
-				"ID" => {
-					registers.insert(op.register, op.data.clone());
+			}
+			"RETURN" => {
+				return get_value(&op.data, &registers, &variables, program);
+			}
+			"FUNC_ARGS" => {}
+			"FUNC_DEF" => {}
+			"END_FUNC" => {
+				if func_stack.len() > 0 {
+					func_stack.pop();
+				}else{
+					break;
 				}
-				"Comma" => {
-					registers.insert(op.register, Data::Comma(Box::new(op.data.clone()), Box::new(op.data2.clone())));
+			}
+			"PLUS" | "MINUS" | "MULT" | "DIV" | "EXP" | "GT" | "LT" | "EQ" | "AND" | "OR" | "DOT" => {
+				let left = get_value(&op.data, &registers, &variables, program)?;
+				let right = get_value(&op.data2, &registers, &variables, program)?;
+				registers.insert(op.register, data_operation(left, right, op.instruction.clone())?);
+				//println!("{:?}", registers.get(&Data::Register(op.register)));
+			}
+			"INCR" => {
+				//Stat2 points INCR at the ID's register, which holds the variable
+				let mut target = op.data.clone();
+				if let Data::Register(reg) = target {
+					target = registers.get(&reg).cloned().unwrap_or(Data::Null);
 				}
-				"FUNC" => {
-					if let Data::Function(func, _args) = get_value(&op.data, &registers, &variables)?{
-						let data = run_builtin(func.as_str(), unwrap_function_inputs(&op.data2, &registers, &variables)?, &registers, &variables, program)?;
-						if data.is_none() {
-							if program.functions.contains_key(&func) {
-								registers.insert(op.register, interpret_program(program, &func)?);
-							}else{
-								panic!("ERROR: FUNCTION {} DOES NOT EXIST ON LINE {}", func, op.line);
-							}
-						}else{
-							registers.insert(op.register, data.unwrap());
-						}
-					} else {
-						panic!("ERROR: {} IS NOT A DATATYPE THAT CAN BE A FUNCTION ON LINE {}", op.data, op.line);
-					}
+				if let Data::Variable(data) = &target {
+					let value = data_operation(get_value(&target, &registers, &variables, program)?, Data::Null, op.instruction.clone())?;
+					set_variable(data, value, &registers, &mut variables, program)?;
 				}
-				"ARG" => {}
-				"FUNC_ARGS" => {}
-				"FUNC_DEF" => {}
-				"END_FUNC" => {
-					if func_stack.len() > 0 {
-						func_stack.pop();
-					}else{
-						jump_point = 0;
-						break;
+			}
+			"WITH" => {
+				//runs the block once for every instance the value stands for, as that instance, or skips it if there aren't any
+				let instances = program.instances_of(&get_value(&op.data, &registers, &variables, program)?);
+				if instances.len() == 0 {
+					if let Data::Label(label) = op.data2 {
+						pc = function.labels[label];
 					}
+				} else {
+					program.enter_context(instances[0]);
+					with_stack.push((instances, 0));
 				}
-				"PLUS" | "MINUS" | "MULT" | "DIV" | "EXP" | "GT" | "LT" | "EQ" | "AND" | "OR" | "DOT" => {
-					let left = get_value(&op.data, &registers, &variables)?;
-					let right = get_value(&op.data2, &registers, &variables)?;
-					registers.insert(op.register, data_operation(left, right, op.instruction.clone())?);
-					//println!("{:?}", registers.get(&Data::Register(op.register)));
-				}
-				"INCR" => {
-					if let Data::Variable(data) = &op.data {
-						variables.get_mut(data).unwrap().1 = data_operation(get_value(&op.data, &registers, &variables)?, Data::Null, op.instruction.clone())?;
+			}
+			"WITH_NEXT" => {
+				program.exit_context();
+				if let Some((instances, index)) = with_stack.last_mut() {
+					*index += 1;
+					if *index < instances.len() {
+						program.enter_context(instances[*index]);
+						if let Data::Label(label) = op.data2 {
+							pc = function.labels[label];
+						}
+					} else {
+						with_stack.pop();
 					}
 				}
-				"CHECK_IF_NOT" => {
-					match get_value(&op.data, &registers, &variables)? {
-						Data::Null => {
-							if let Data::Label(label) = op.data2 {
-								jump_point = program.labels[label];
-							}
+			}
+			"CHECK_IF_NOT" => {
+				match get_value(&op.data, &registers, &variables, program)? {
+					Data::Null => {
+						if let Data::Label(label) = op.data2 {
+							pc = function.labels[label];
 						}
-						Data::Int(i) if i == 0 => {
-							if let Data::Label(label) = op.data2 {
-								jump_point = program.labels[label];
-							}
+					}
+					Data::Int(i) if i == 0 => {
+						if let Data::Label(label) = op.data2 {
+							pc = function.labels[label];
 						}
-						Data::Decimal(i) if i == Decimal::from(0) => {
-							if let Data::Label(label) = op.data2 {
-								jump_point = program.labels[label];
-							}
+					}
+					Data::Decimal(i) if i == Decimal::from(0) => {
+						if let Data::Label(label) = op.data2 {
+							pc = function.labels[label];
 						}
-						_ => {}
 					}
+					_ => {}
 				}
-				"CHECK_ELSE_NOT" => {
-					match get_value(&op.data, &registers, &variables)? {
-						Data::Null => {
-							if let Data::Label(label) = op.data2 {
-								jump_point = program.labels[label];
-							}
+			}
+			"CHECK_ELSE_NOT" => {
+				match get_value(&op.data, &registers, &variables, program)? {
+					Data::Null => {
+						if let Data::Label(label) = op.data2 {
+							pc = function.labels[label];
 						}
-						Data::Int(i) if i == 0 => {
-							if let Data::Label(label) = op.data2 {
-								jump_point = program.labels[label];
-							}
+					}
+					Data::Int(i) if i == 0 => {
+						if let Data::Label(label) = op.data2 {
+							pc = function.labels[label];
 						}
-						Data::Decimal(i) if i == Decimal::from(0) => {
-							if let Data::Label(label) = op.data2 {
-								jump_point = program.labels[label];
-							}
+					}
+					Data::Decimal(i) if i == Decimal::from(0) => {
+						if let Data::Label(label) = op.data2 {
+							pc = function.labels[label];
 						}
-						_ => {}
 					}
-					//println!("else: {}, {}", position, jump_point);
+					_ => {}
 				}
-				"FOR_GOTO" => {
-					match get_value(&op.data, &registers, &variables)? {
-						Data::Null => {}
-						Data::Int(i) if i == 0 => {}
-						_ => {
-							if let Data::Label(label) = op.data2 {
-								jump_point = program.labels[label];
-							}
+			}
+			"FOR_GOTO" => {
+				match get_value(&op.data, &registers, &variables, program)? {
+					Data::Null => {}
+					Data::Int(i) if i == 0 => {}
+					Data::Decimal(i) if i == Decimal::from(0) => {}
+					_ => {
+						if let Data::Label(label) = op.data2 {
+							pc = function.labels[label];
 						}
 					}
 				}
-				opcode => {
-					println!("UNKNOWN OPCODE ON LINE {}. OPCODE IS {}", op.line, opcode);
-				}
 			}
-			if jump_point != 0 {
-				break;
+			opcode => {
+				println!("UNKNOWN OPCODE ON LINE {}. OPCODE IS {}", op.line, opcode);
 			}
-			_position += 1;
 		}
 	}
 	return Ok(Data::Null);
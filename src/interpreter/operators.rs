@@ -82,6 +82,14 @@ pub fn data_operation(left: Data, right: Data, op: String) -> Data {
 		(Data::Decimal(l), Data::Null, "INCR") => {
 			return Data::Decimal(l + dec!(1));
 		}
+
+		//+ with a string on either side joins them, which is what "hp: {hp}" turns into
+		(Data::String(l), r, "PLUS") => {
+			return Data::String(format!("{}{}", l, r));
+		}
+		(l, Data::String(r), "PLUS") => {
+			return Data::String(format!("{}{}", l, r));
+		}
 		_ => {
 			return Data::Null;
 		}
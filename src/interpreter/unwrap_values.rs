@@ -1,25 +1,85 @@
-
 use std::collections::HashMap;
 use crate::datatypes::Data;
+use crate::datatypes::Program;
 //use crate::datatypes::Opcode;
 
-pub fn get_value(data: &Data, registers: &HashMap<u32, Data>, variables: &HashMap<String, (Data, Data)>) -> Result<Data, String>{
+//Where a variable lives:
+//	a plain name is a local if it was declared (with var or a type) or is an argument
+//	self.name, other.name and instance.name belong to that instance
+//GML code (program.gml_scoping) also follows GML's rules:
+//	a plain name that isn't a local belongs to self
+//	global.name is shared by the whole program
+enum Scope {
+	Local,
+	Global,
+	Instance(usize),
+}
+
+fn find_scope<'a>(name: &'a str, registers: &HashMap<u32, Data>, variables: &HashMap<String, (Data, Data)>, program: &Program) -> Result<(Scope, &'a str), String> {
+	let (owner, field) = match name.rsplit_once('.') {
+		Some(split) => split,
+		None => {
+			if variables.contains_key(name) || !program.gml_scoping {
+				return Ok((Scope::Local, name));
+			}
+			return Ok((Scope::Instance(program.get_self()), name));
+		}
+	};
+	match owner {
+		"global" if program.gml_scoping => {
+			return Ok((Scope::Global, field));
+		}
+		"self" => {
+			return Ok((Scope::Instance(program.get_self()), field));
+		}
+		"other" => {
+			return Ok((Scope::Instance(program.get_other()), field));
+		}
+		_ => {
+			match get_value(&Data::Variable(owner.to_string()), registers, variables, program)? {
+				Data::Object(id) => {
+					return Ok((Scope::Instance(id), field));
+				}
+				data => {
+					return Err(format!("{} IS {}, WHICH DOESN'T HAVE VARIABLES TO GET {} FROM", owner, data, field));
+				}
+			}
+		}
+	}
+}
+
+pub fn get_value(data: &Data, registers: &HashMap<u32, Data>, variables: &HashMap<String, (Data, Data)>, program: &Program) -> Result<Data, String>{
 	match data {
 		Data::Register(true_data) => {
 			let reg = registers.get(&true_data);
 			if !reg.is_some() {
 				return Err(format!("NONEXISTENT REGISTER ACCESS TRYING TO ACCESS {:?}", data));
 			}
-			return get_value(&reg.unwrap(), registers, variables);
+			return get_value(&reg.unwrap(), registers, variables, program);
 		}
 		Data::Variable(true_data) => {
-			let var = variables.get(true_data);
-			if !var.is_some() {
-				//probably a function, regardless just return null to make it happy
-				//return Data::Null;
-				return Err(format!("NONEXISTENT VARIABLE ACCESS TRYING TO ACCESS {:?}", data));
+			//functions imported from a module are called as module.name, which would otherwise look like a variable of an instance
+			if true_data.contains('.') && program.functions.contains_key(true_data) {
+				return Ok(Data::Function(true_data.to_owned(), Box::new(vec![])));
+			}
+			let var = match find_scope(true_data, registers, variables, program)? {
+				(Scope::Local, name) => variables.get(name).map(|var| &var.1),
+				(Scope::Global, name) => program.globals.get(name),
+				(Scope::Instance(id), name) => program.get_object(id).and_then(|object| object.data.get(name)),
+			};
+			if let Some(var) = var {
+				return get_value(var, registers, variables, program);
+			}
+			//names that aren't variables can still be the current instances or a function defined in the program
+			match true_data.as_str() {
+				"self" => return Ok(Data::Object(program.get_self())),
+				"other" => return Ok(Data::Object(program.get_other())),
+				_ => {}
+			}
+			if program.functions.contains_key(true_data) {
+				return Ok(Data::Function(true_data.to_owned(), Box::new(vec![])));
 			}
-			return get_value(&var.unwrap().1, registers, variables);
+			return Err(format!("NONEXISTENT VARIABLE ACCESS TRYING TO ACCESS {:?}", data));
 		}
 		_ => {
 			return Ok(data.clone());
@@ -27,15 +87,45 @@ pub fn get_value(data: &Data, registers: &HashMap<u32, Data>, variables: &HashMa
 	};
 }
 
-pub fn unwrap_function_inputs(data: &Data, registers: &HashMap<u32, Data>, variables: &HashMap<String, (Data, Data)>) -> Result<Vec<Data>, String> {
+//Assigning to a plain name that was never declared makes it a variable of self in GML, and is an error otherwise
+pub fn set_variable(name: &str, value: Data, registers: &HashMap<u32, Data>, variables: &mut HashMap<String, (Data, Data)>, program: &mut Program) -> Result<(), String> {
+	match find_scope(name, registers, variables, program)? {
+		(Scope::Local, name) => {
+			match variables.get_mut(name) {
+				Some(var) => {
+					var.1 = value;
+				}
+				None => {
+					return Err(format!("NONEXISTENT VARIABLE ACCESS TRYING TO SET {:?}", name));
+				}
+			}
+		}
+		(Scope::Global, name) => {
+			program.globals.insert(name.to_owned(), value);
+		}
+		(Scope::Instance(id), field) => {
+			match program.get_object_mut(id) {
+				Some(object) => {
+					object.data.insert(field.to_owned(), value);
+				}
+				None => {
+					return Err(format!("THERE IS NO INSTANCE {} TO SET {} ON", id, name));
+				}
+			}
+		}
+	}
+	return Ok(());
+}
+
+pub fn unwrap_function_inputs(data: &Data, registers: &HashMap<u32, Data>, variables: &HashMap<String, (Data, Data)>, program: &Program) -> Result<Vec<Data>, String> {
 	let mut ret_val = vec![];
 	match data {
 		Data::Comma(l, r) => {
-			ret_val.append(&mut unwrap_function_inputs(l, registers, variables)?);
-			ret_val.append(&mut unwrap_function_inputs(r, registers, variables)?);
+			ret_val.append(&mut unwrap_function_inputs(l, registers, variables, program)?);
+			ret_val.append(&mut unwrap_function_inputs(r, registers, variables, program)?);
 		}
 		Data::Register(_) | Data::Variable(_) => {
-			ret_val.append(&mut unwrap_function_inputs(&get_value(data, registers, variables)?, registers, variables)?);
+			ret_val.append(&mut unwrap_function_inputs(&get_value(data, registers, variables, program)?, registers, variables, program)?);
 		}
 		Data::Null => {
 		}
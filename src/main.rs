@@ -31,55 +31,123 @@
 
 use std::env;
 use macroquad::prelude::*;
-use crate::datatypes::Console;
+use crate::datatypes::{Console, LogLevel};
 use crate::parser::parser_setup::*;
 use crate::interpreter::interpreter::interpret_program;
 use crate::console::*;
+use crate::bytecode::gelc::save_program;
+use crate::bytecode::disassembler::disassemble;
+use crate::hot_reload::*;
+use crate::console_commands::Commands;
+use crate::console_history::*;
 
 mod datatypes;
 mod parser;
 mod scanner;
 mod optimizers;
 mod interpreter;
+mod parse_table;
 mod grammar_generator;
 mod console;
+mod bytecode;
+mod hot_reload;
+mod console_commands;
+mod console_history;
 
-#[macroquad::main("GEL")]
-async fn main() {
+//The command line tools run here, before macroquad opens a window, so they work without a display
+fn main() {
     std::env::set_var("RUST_BACKTRACE", "1");
+    let mut args: Vec<String> = env::args().collect();
+    //gel --dialect dialects/name ... compiles with dialects/name.grammar and dialects/name.tokens instead of going by the file's extension
+    let mut dialect = None;
+    if args.len() > 2 && args[1] == "--dialect" {
+        match load_dialect(&args[2]) {
+            Ok(loaded) => {
+                dialect = Some((loaded, args[2].to_owned()));
+                args.drain(1..3);
+            }
+            Err(err) => {
+                println!("Error: {}", err);
+                return;
+            }
+        }
+    }
+    //gel --compile file.gel [file.gelc] writes the compiled program instead of running it
+    if args.len() > 2 && args[1] == "--compile" {
+        let output = if args.len() > 3 { args[3].to_owned() } else if args[2].ends_with(".gel") { format!("{}c", args[2]) } else { format!("{}.gelc", args[2]) };
+        match try_compile_file_with(&args[2], dialect.as_ref().map_or(dialect_for_file(&args[2]), |(dialect, _)| dialect)) {
+            Ok(program) => {
+                if let Err(err) = save_program(&program, &output) {println!("Error: {}", err);}
+            }
+            Err(err) => {println!("Error: {}", err);}
+        }
+        return;
+    }
+    //gel --disasm file.gel prints the compiled opcodes instead of running them
+    if args.len() > 2 && args[1] == "--disasm" {
+        match try_compile_file_with(&args[2], dialect.as_ref().map_or(dialect_for_file(&args[2]), |(dialect, _)| dialect)) {
+            Ok(program) => {println!("{}", disassemble(&program));}
+            Err(err) => {println!("Error: {}", err);}
+        }
+        return;
+    }
+    macroquad::Window::new("GEL", run(args, dialect));
+}
+
+async fn run(args: Vec<String>, dialect: Option<(Dialect, String)>) {
     let mut programs = vec![];
-    let args: Vec<String> = env::args().collect();
     if args.len() > 1 {
-        programs.push(compile_file(&args[1]));
+        let mut program = compile_file_with(&args[1], dialect.as_ref().map_or(dialect_for_file(&args[1]), |(dialect, _)| dialect));
+        //so reloading it uses the same dialect
+        if let (Some(source), Some((_, path))) = (&mut program.source, &dialect) {
+            source.dialect = Some(path.to_owned());
+        }
+        programs.push(program);
     }
     let mut copy = String::new();
     let mut current_frame = 0;
-    let mut console = Console { open: false, just_opened: false, console_text: String::new(), console_log: vec![], console_history: vec![], index: 0 };
+    let mut console = Console::default();
+    load_history(&mut console, history_file());
+    //the host can add its own console commands here with commands.register
+    let commands = Commands::new();
     loop {
         if console.open {
             if is_key_pressed(KeyCode::Tab) {
-                console_autocomplete(&mut console, &mut programs);
+                console_autocomplete(&mut console, &mut programs, &commands);
             }
             if is_key_pressed(KeyCode::Enter) {
-                console_submit(&mut console, &mut programs);
+                console_submit(&mut console, &mut programs, &commands);
+            }
+        }
+
+        //loaded files that changed get their new code swapped in
+        if current_frame % WATCH_INTERVAL == 0 {
+            for program in &mut programs {
+                match reload_if_changed(program) {
+                    Some(Ok(message)) => {console.log(LogLevel::Info, Some(program.name()), message);}
+                    //the old code is still running, so it's only a warning
+                    Some(Err(err)) => {console.log(LogLevel::Warning, Some(program.name()), err);}
+                    None => {}
+                }
             }
         }
 
+        console.frame = current_frame;
         for program in &mut programs {
             program.current_frame = current_frame;
             if !program.initialized {
-                if let Err(err) = interpret_program(program, "") {console.console_log.push((format!("Error: {}", err), 600));}
-                if let Err(err) = interpret_program(program, "init") {console.console_log.push((format!("Error: {}", err), 600));}
+                if let Err(err) = interpret_program(program, "") {console.log(LogLevel::Error, Some(program.name()), format!("Error: {}", err));}
+                if let Err(err) = interpret_program(program, "init") {console.log(LogLevel::Error, Some(program.name()), format!("Error: {}", err));}
                 program.initialized = true;
             }
         }
 
         for program in &mut programs {
-	        if let Err(err) = interpret_program(program, "step") {console.console_log.push((format!("Error: {}", err), 600));}
+	        if let Err(err) = interpret_program(program, "step") {console.log(LogLevel::Error, Some(program.name()), format!("Error: {}", err));}
         }
 
         for program in &mut programs {
-	        if let Err(err) = interpret_program(program, "draw") {console.console_log.push((format!("Error: {}", err), 600));}
+	        if let Err(err) = interpret_program(program, "draw") {console.log(LogLevel::Error, Some(program.name()), format!("Error: {}", err));}
         }
 
         for program in &mut programs {
@@ -109,6 +177,14 @@ fn unittest1(){
     assert!(program.log == vec!["1: this should print", "3: this should print"])
 }
 
+#[test]
+fn for_loop_test(){
+    //the condition is checked before every run, so i < 4 runs exactly 4 times and j < 0 never runs
+    let mut program = compile_file("test_files/for_loop.gel");
+    if let Err(err) = interpret_program(&mut program, "init") {println!("{}", format!("Error: {}", err));}
+    assert!(program.log == vec!["0", "1", "2", "3", "done"])
+}
+
 #[test]
 fn unittest2(){
     let mut program = compile_file("test_files/unittest2.gel");
@@ -118,4 +194,375 @@ fn unittest2(){
         println!("{}", message.to_owned());
     }
     assert!(program.log == vec!["1", "0"])
-}
\ No newline at end of file
+}
+
+#[test]
+fn function_body_test(){
+    //{}, : and bodies that go until the next definition can all be mixed
+    let source = "#define init{\n\tprint(add(1))\n\tprint(add(1, 5))\n\tnamed()\n}\n#define add(a, int b = 2)\n\tprint(a)\n\treturn a + b\n#define named:\n\tprint(\"named\")\n";
+    let mut program = try_compile(source.to_string()).unwrap();
+    if let Err(err) = interpret_program(&mut program, "init") {println!("{}", format!("Error: {}", err));}
+    assert!(program.log == vec!["1", "3", "1", "6", "named"]);
+
+    let error = try_compile("#define init{\n\tprint(1)\n}\nprint(2)\n#define other\n\tprint(3)".to_string()).err().unwrap();
+    assert!(error.contains("The body of init is in {}, but there's more code after it on line 4"));
+
+    //top level code goes before a {} function instead of after it
+    let mut program = compile_file("test_files/randomtest.gel");
+    interpret_program(&mut program, "").unwrap();
+    assert!(program.log.len() == 3);
+}
+
+#[test]
+fn module_test(){
+    let mut program = try_compile_file("test_files/modules/main.gel").unwrap();
+    if let Err(err) = interpret_program(&mut program, "init") {println!("{}", format!("Error: {}", err));}
+    assert!(program.log == vec!["10", "enemy with 5 hp", "2"]);
+    //util.gel is imported twice but only compiled once, and enemies.gel's helper stays in its namespace
+    assert!(std::rc::Rc::ptr_eq(&program.functions["clamp"], &program.functions["util.clamp"]));
+    assert!(program.functions.contains_key("enemies.helper") && !program.functions.contains_key("helper"));
+
+    //a local with the same name as one of the module's functions stays a local
+    let mut program = try_compile("import \"test_files/modules/lib/shadow.gel\"\n#define init{\n\tprint(shadow.total())\n}".to_string()).unwrap();
+    interpret_program(&mut program, "init").unwrap();
+    assert!(program.log == vec!["6"]);
+
+    assert!(try_compile_file("test_files/modules/cycle_a.gel").err().unwrap().contains("Import cycle"));
+    assert!(try_compile("from \"test_files/modules/util.gel\" import nope".to_string()).err().unwrap().contains("doesn't have a function called nope"));
+    //importing a name the file defines itself would quietly replace it
+    assert!(try_compile("from \"test_files/modules/util.gel\" import clamp\n#define clamp(value)\n\treturn value".to_string()).err().unwrap().contains("clamp on line 1 is already defined"));
+    assert!(try_compile("import \"missing.gel\"".to_string()).err().unwrap().contains("Couldn't find missing.gel"));
+}
+
+#[test]
+fn hot_reload_test(){
+    let filename = std::env::temp_dir().join("gel_hot_reload_test.gml").display().to_string();
+    std::fs::write(&filename, "#define init{\n\tglobal.count = 1\n\tenemy = instance_create(1, 2, \"enemy\")\n}\n#define step{\n\tprint(global.count)\n}").unwrap();
+    let mut program = compile_file(&filename);
+    interpret_program(&mut program, "init").unwrap();
+    interpret_program(&mut program, "step").unwrap();
+    assert!(reload_if_changed(&mut program).is_none());
+
+    //the new step runs, but what init made is still there
+    std::fs::write(&filename, "#define step{\n\tprint(global.count + 10)\n\tprint(enemy.x)\n}").unwrap();
+    program.source.as_mut().unwrap().files[0].1 = None;
+    assert!(reload_if_changed(&mut program).unwrap().is_ok());
+    interpret_program(&mut program, "step").unwrap();
+    assert!(!program.functions.contains_key("init"));
+
+    //broken code leaves the old code running
+    std::fs::write(&filename, "#define step{\n\tprint(").unwrap();
+    assert!(reload_program(&mut program).err().unwrap().contains("the old code is still running"));
+    interpret_program(&mut program, "step").unwrap();
+    assert!(program.log == vec!["1", "11", "1", "11", "1"]);
+    let _ = std::fs::remove_file(&filename);
+
+    //a file that doesn't compile when it's loaded is still watched, and saving a fix loads it
+    let filename = std::env::temp_dir().join("gel_hot_reload_broken_test.gel").display().to_string();
+    std::fs::write(&filename, "#define init{\n\tprint(").unwrap();
+    let mut program = compile_file(&filename);
+    assert!(program.functions.is_empty() && program.errors.len() == 1);
+    assert!(program.name() == filename);
+    program.initialized = true;
+    std::fs::write(&filename, "#define init{\n\tprint(\"fixed\")\n}").unwrap();
+    program.source.as_mut().unwrap().files[0].1 = None;
+    assert!(reload_if_changed(&mut program).unwrap().is_ok());
+    assert!(!program.initialized);
+    interpret_program(&mut program, "init").unwrap();
+    assert!(program.log == vec!["fixed"]);
+    let _ = std::fs::remove_file(&filename);
+}
+
+#[test]
+fn autocomplete_test(){
+    let mut program = compile_file("test_files/unittest3.gel");
+    interpret_program(&mut program, "").unwrap();
+    interpret_program(&mut program, "init").unwrap();
+    let programs = vec![program];
+    let commands = Commands::new();
+    assert!(completions("/lo", &programs, &commands) == vec!["/load "]);
+    assert!(completions("/gel draw_te", &programs, &commands) == vec!["/gel draw_text"]);
+    assert!(completions("/run x = instance_cr", &programs, &commands) == vec!["/run x = instance_create"]);
+    assert!(completions("/load test_files/unittest", &programs, &commands).contains(&"/load test_files/unittest3.gel".to_string()));
+    assert!(completions("/load test_fil", &programs, &commands) == vec!["/load test_files/"]);
+    assert!(completions("/gel ini", &programs, &commands) == vec!["/gel init"]);
+    assert!(completions("hello", &programs, &commands).is_empty());
+    assert!(crate::interpreter::builtin_functions::builtin_signatures("instance_create").len() > 0);
+}
+
+//Types text into a cleared console and presses Enter, handing back what it logged
+#[cfg(test)]
+fn submit(text: &str, console: &mut Console, programs: &mut Vec<datatypes::Program>, commands: &Commands) -> Vec<String> {
+    console.console_log.clear();
+    console.console_text = text.to_string();
+    console_submit(console, programs, commands);
+    return console.console_log.iter().map(|entry| entry.text.to_owned()).collect();
+}
+
+#[test]
+fn console_commands_test(){
+    let mut console = Console::default();
+    let mut commands = Commands::new();
+    commands.register("twice", &["2x"], "/twice <text> - says it twice", |text, console, _, _| {
+        console.info(format!("{} {}", text, text));
+        return Ok(());
+    });
+    let mut script = try_compile("console_command(\"greet\", \"greet\", \"/greet <name> - says hi\")\n#define greet(name)\n\tprint(\"hi \" + name)".to_string()).unwrap();
+    interpret_program(&mut script, "").unwrap();
+    let mut programs = vec![compile_file("test_files/unittest3.gel"), script];
+    let mut run = |text: &str, programs: &mut Vec<datatypes::Program>| submit(text, &mut console, programs, &commands);
+    assert!(run("/gel print(1 + 1)", &mut programs) == vec!["2"]);
+    assert!(run("/2x hey", &mut programs) == vec!["hey hey"]);
+    assert!(run("/list", &mut programs) == vec!["0: test_files/unittest3.gel", "1: (not from a file)"]);
+    assert!(run("/help greet", &mut programs) == vec!["/greet <name> - says hi (from (not from a file))"]);
+    assert!(run("/help", &mut programs).contains(&"/greet <name> - says hi".to_string()));
+    assert!(run("/? cls", &mut programs) == vec!["/clear - clears the console", "Also called /cls"]);
+    run("/greet you", &mut programs);
+    assert!(programs[1].log.last().unwrap() == "hi you");
+    //bad arguments and missing functions are errors instead of crashing or doing nothing
+    assert!(run("/gel console_command(1)", &mut programs)[0].contains("console_command takes (name, function[, usage]) strings"));
+    run("/gel console_command(\"wave\", \"wave\")", &mut programs);
+    assert!(run("/wave", &mut programs) == vec!["Error: /wave runs wave, but (not from a file) doesn't have a function called that"]);
+    assert!(run("/nope", &mut programs) == vec!["Error: There's no /nope command, /help lists them"]);
+    assert!(run("/unload 5", &mut programs) == vec!["Error: There is no loaded program 5, there are 2 loaded"]);
+    assert!(run("/unload 0", &mut programs) == vec!["Unloaded test_files/unittest3.gel"]);
+    assert!(programs.len() == 1);
+    assert!(run("just chatting", &mut programs) == vec!["just chatting"]);
+}
+
+#[test]
+fn console_repl_test(){
+    let mut console = Console::default();
+    let commands = Commands::new();
+    let mut game = try_compile_with("global.score = 10\nhp = 3\n#define hurt{\n\thp = hp - argument0\n}".to_string(), gml_dialect()).unwrap();
+    interpret_program(&mut game, "").unwrap();
+    let mut programs = vec![compile_file("test_files/unittest3.gel"), game];
+    let mut run = |text: &str, programs: &mut Vec<datatypes::Program>| submit(text, &mut console, programs, &commands);
+    //code runs in the last loaded program, and a line that's just a value prints it
+    assert!(run("/gml global.score", &mut programs) == vec!["10"]);
+    assert!(run("/gml global.score = global.score + 5", &mut programs).is_empty());
+    assert!(run("/gml global.score * 2", &mut programs) == vec!["30"]);
+    assert!(run("/gml hurt(1)", &mut programs).is_empty());
+    assert!(run("/gml hp", &mut programs) == vec!["2"]);
+    assert!(programs[1].globals["score"].to_string() == "15");
+    assert!(!programs[1].functions.contains_key("/console"));
+    //functions defined in the console are added to the program
+    run("/gml #define heal\n\thp = hp + 10", &mut programs);
+    assert!(run("/gml heal()\nprint(hp)", &mut programs) == vec!["12"]);
+    assert!(run("/gml print(", &mut programs)[0].starts_with("Error: "));
+
+    assert!(run("/use 0", &mut programs) == vec!["Console code runs in test_files/unittest3.gel"]);
+    run("/gel self.score = 1", &mut programs);
+    assert!(run("/gel self.score", &mut programs) == vec!["1"]);
+    //unittest3.gel is GEL, which doesn't have GML's globals
+    assert!(run("/gel global.score = 1", &mut programs)[0].starts_with("Error: "));
+    assert!(run("/gml global.score = 1", &mut programs) == vec!["Error: test_files/unittest3.gel is a GEL program, run code in it with /gel"]);
+    run("/unload 0", &mut programs);
+    assert!(run("/gml global.score", &mut programs) == vec!["15"]);
+    assert!(run("/gel global.score", &mut programs) == vec!["Error: (not from a file) is a GML program, run code in it with /gml"]);
+    programs.clear();
+    assert!(run("/gel 1 + 2", &mut programs) == vec!["3"]);
+    //on its own /gml code still gets GML's globals
+    assert!(run("/gml global.a = 4\nprint(global.a)", &mut programs) == vec!["4"]);
+}
+
+#[test]
+fn console_history_test(){
+    let file = std::env::temp_dir().join("gel_history_test");
+    let _ = std::fs::remove_file(&file);
+    let mut console = Console::default();
+    load_history(&mut console, Some(file.to_owned()));
+    let commands = Commands::new();
+    let mut programs = vec![];
+    for text in ["/gel print(1)", "/list", "/gel print(2)", "/gel print(1)", ""] {
+        console.console_text = text.to_string();
+        console_submit(&mut console, &mut programs, &commands);
+    }
+    //each command is in there once, and only once
+    assert!(console.console_history == vec!["/list", "/gel print(2)", "/gel print(1)"]);
+
+    //it's still there next time
+    let mut console = Console::default();
+    load_history(&mut console, Some(file.to_owned()));
+    assert!(console.console_history == vec!["/list", "/gel print(2)", "/gel print(1)"]);
+
+    //Ctrl+R finds the newest match first, then older ones, and Escape puts back what was typed
+    console.console_text = "print".to_string();
+    search_step(&mut console);
+    assert!(search_text(&mut console).unwrap() == "(reverse-i-search)`print': /gel print(1)");
+    search_step(&mut console);
+    search_step(&mut console);
+    assert!(search_text(&mut console).unwrap() == "(reverse-i-search)`print': /gel print(2)");
+    console.console_text = "nope".to_string();
+    assert!(search_text(&mut console).unwrap() == "(failed reverse-i-search)`nope'");
+    end_search(&mut console, false);
+    assert!(console.console_text == "print" && console.search.is_none());
+    console.console_text = "li".to_string();
+    search_step(&mut console);
+    console_submit(&mut console, &mut programs, &commands);
+    assert!(console.console_log.last().unwrap().text == "No programs are loaded");
+
+    for index in 0..HISTORY_LIMIT + 5 {
+        add_history(&mut console, &format!("/gel print({})", index));
+    }
+    assert!(console.console_history.len() == HISTORY_LIMIT);
+    assert!(std::fs::read_to_string(&file).unwrap().lines().count() == HISTORY_LIMIT);
+    let _ = std::fs::remove_file(&file);
+}
+
+#[test]
+fn console_log_test(){
+    let mut console = Console::default();
+    let commands = Commands::new();
+    let mut programs = vec![compile_file("test_files/unittest3.gel")];
+    let name = Some("test_files/unittest3.gel".to_string());
+    //a step and a draw error every frame are two lines, counting how many times they happened
+    for frame in 0..30 {
+        console.frame = frame;
+        console.log(LogLevel::Error, name.clone(), "Error: step broke".to_string());
+        console.log(LogLevel::Error, name.clone(), "Error: draw broke".to_string());
+    }
+    assert!(console.console_log.len() == 2);
+    assert!(console.console_log[0].repeats == 29 && console.console_log[0].frame == 29);
+    assert!(log_line(&console.console_log[1]).starts_with("[f29 "));
+    assert!(log_line(&console.console_log[1]).ends_with("s] test_files/unittest3.gel: Error: draw broke (x30)"));
+
+    console.log(LogLevel::Warning, name.clone(), "careful".to_string());
+    console.log(LogLevel::Info, Some("other.gel".to_string()), "hi".to_string());
+    let logged = console.console_log.clone();
+    let mut warnings = vec![];
+    let mut filter = |text: &str, programs: &mut Vec<datatypes::Program>| {
+        warnings.append(&mut submit(text, &mut console, programs, &commands));
+        return logged.iter().filter(|entry| console.shows(entry)).map(|entry| entry.text.to_owned()).collect::<Vec<String>>();
+    };
+    assert!(filter("/filter errors", &mut programs) == vec!["careful", "hi"]);
+    assert!(filter("/filter 0", &mut programs) == vec!["hi"]);
+    assert!(filter("/filter other.gel", &mut programs).is_empty());
+    assert!(filter("/filter error", &mut programs).is_empty());
+    assert!(filter("/filter", &mut programs).len() == 4);
+    assert!(warnings.contains(&"other.gel isn't loaded, hiding its messages anyway".to_string()));
+
+    //a file that doesn't compile is logged as an error, so /filter errors hides it
+    submit("/filter", &mut console, &mut programs, &commands);
+    submit("/load test_files/modules/cycle_a.gel", &mut console, &mut programs, &commands);
+    console_log(&mut console, &mut programs[1]);
+    let entry = console.console_log.last().unwrap().clone();
+    assert!(entry.level == LogLevel::Error && entry.text.contains("Import cycle"));
+    submit("/filter errors", &mut console, &mut programs, &commands);
+    assert!(!console.shows(&entry));
+
+    for index in 0..LOG_LIMIT + 10 {
+        console.info(index.to_string());
+    }
+    assert!(console.console_log.len() == LOG_LIMIT);
+}
+
+#[test]
+fn disassembler_test(){
+    let program = try_compile("if(x)\n\tprint(\"yes\")\n#define add(a, b)\n\treturn a + b".to_string()).unwrap();
+    let listing = crate::bytecode::disassembler::disassemble(&program);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert!(lines[..5] == ["function <root> (2 opcodes, 2 labels)", "       0           CHECK_IF_NOT   $x, L0 (-> 2)                    ; line 1", "       1  r5 =     FUNC           $print, \"yes\"                    ; line 2", "  L0:", "  L1:"]);
+    assert!(lines[6..9] == ["function add (6 opcodes, 0 labels)", "    ; takes (<var>, <var>) returns null", "       0           Declare        <var>, $a                        ; line 3"]);
+    assert!(lines[12..14] == ["       4  r9 =     PLUS           $a, $b                           ; line 4", "       5           RETURN         r9                               ; line 4"]);
+
+    //the console shows the same listing for a loaded program
+    let mut console = Console::default();
+    let mut programs = vec![program];
+    Commands::new().run("/disasm 0", &mut console, &mut programs).unwrap();
+    assert!(console.console_log.iter().map(|entry| entry.text.to_owned()).collect::<Vec<String>>() == listing.lines().map(|line| line.to_string()).collect::<Vec<String>>());
+    assert!(Commands::new().run("/disasm 3", &mut console, &mut programs).is_err());
+}
+
+#[test]
+fn gelc_roundtrip(){
+    let program = compile_file("test_files/unittest3.gel");
+    let bytes = crate::bytecode::gelc::serialize_program(&program);
+    let mut loaded = crate::bytecode::gelc::deserialize_program(&bytes).unwrap();
+    assert!(crate::bytecode::gelc::serialize_program(&loaded) == bytes);
+    if let Err(err) = interpret_program(&mut loaded, "") {println!("{}", format!("Error: {}", err));}
+    if let Err(err) = interpret_program(&mut loaded, "init") {println!("{}", format!("Error: {}", err));}
+    assert!(loaded.log == vec!["root", "0", "one", "2", "done"]);
+    assert!(crate::bytecode::gelc::deserialize_program(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn unittest3(){
+    let mut program = compile_file("test_files/unittest3.gel");
+    println!("{:#?}", program);
+    if let Err(err) = interpret_program(&mut program, "") {println!("{}", format!("Error: {}", err));}
+    if let Err(err) = interpret_program(&mut program, "init") {println!("{}", format!("Error: {}", err));}
+    for message in &program.log {
+        println!("{}", message.to_owned());
+    }
+    assert!(program.log == vec!["root", "0", "one", "2", "done"])
+}
+
+#[test]
+fn string_test(){
+    let mut program = compile_file("test_files/strings.gel");
+    if let Err(err) = interpret_program(&mut program, "init") {println!("{}", format!("Error: {}", err));}
+    for message in &program.log {
+        println!("{}", message.to_owned());
+    }
+    assert!(program.log == vec!["hp: 7/10", "say \"hi\"\tnow", "14 {not code} caf\u{e9} \u{1F600}", "C:\\games\\{hp}", "line one\nline two", "3 left"])
+}
+
+#[test]
+fn unary_minus_test(){
+    let source = "#define init{\n\tvar a = 4\n\tvar x = -5\n\tprint(x)\n\tprint(-1.5e3)\n\tprint(-(a))\n\tprint(2 - -a)\n\tprint(-a * 2)\n}";
+    let mut program = try_compile(source.to_string()).unwrap();
+    interpret_program(&mut program, "init").unwrap();
+    assert!(program.log == vec!["-5", "-1500", "-4", "6", "-8"]);
+    let mut program = try_compile_with("#define init{\n\tx = -5\n\tprint(-x)\n}".to_string(), gml_dialect()).unwrap();
+    interpret_program(&mut program, "init").unwrap();
+    assert!(program.log == vec!["5"]);
+}
+
+#[test]
+fn dialect_test(){
+    let dialect = load_dialect("test_files/begin_end").unwrap();
+    let source = String::from("#define init begin\n\tif(true) begin print(\"begin\") print(\"end\") end\nend");
+    let mut program = try_compile_with(source, &dialect).unwrap();
+    if let Err(err) = interpret_program(&mut program, "init") {println!("{}", format!("Error: {}", err));}
+    assert!(program.log == vec!["begin", "end"]);
+    assert!(load_dialect("test_files/missing").err().unwrap().contains("test_files/missing.grammar"));
+}
+
+#[test]
+fn unittest_gml(){
+    let mut program = compile_file("test_files/unittest_gml.gml");
+    if let Err(err) = interpret_program(&mut program, "init") {println!("{}", format!("Error: {}", err));}
+    for message in &program.log {
+        println!("{}", message.to_owned());
+    }
+    assert!(program.log == vec!["2", "3", "100", "11", "33", "program", "2"])
+}
+
+#[test]
+fn with_unwind_test(){
+    //an error or a return in the middle of a with still puts self back
+    let source = String::from("#define init{\n\twith(instance_create(0, 0, \"enemy\")) print(missing)\n}\n#define find{\n\twith(instance_create(0, 0, \"enemy\")) return x\n}");
+    let mut program = try_compile_with(source, gml_dialect()).unwrap();
+    let outside = program.get_self();
+    assert!(interpret_program(&mut program, "init").is_err());
+    assert!(program.get_self() == outside);
+    interpret_program(&mut program, "find").unwrap();
+    assert!(program.get_self() == outside);
+}
+
+#[test]
+fn gml_scoping_test(){
+    //globals, argument0 and assigning to undeclared names are only GML's
+    let source = "#define init{\n\tglobal.count = 1\n\thp = argument_count\n}";
+    let mut program = try_compile_with(source.to_string(), gml_dialect()).unwrap();
+    interpret_program(&mut program, "init").unwrap();
+    assert!(program.globals["count"].to_string() == "1");
+    let loaded = crate::bytecode::gelc::deserialize_program(&crate::bytecode::gelc::serialize_program(&program)).unwrap();
+    assert!(loaded.gml_scoping);
+
+    let mut program = try_compile(source.to_string()).unwrap();
+    assert!(interpret_program(&mut program, "init").is_err());
+    let mut program = try_compile("#define init{\n\thp = 3\n}".to_string()).unwrap();
+    assert!(interpret_program(&mut program, "init").err().unwrap().contains("hp"));
+}
@@ -1,6 +1,411 @@
-
 use crate::datatypes::ASTNode;
+use crate::datatypes::Data;
+use crate::interpreter::operators::data_operation;
+use rust_decimal::prelude::*;
+
+//A single optimization over the AST, returning whether it changed anything.
+//Passes work on the parse tree as the parser leaves it, so they have to keep the shapes linearize expects.
+pub struct AstPass {
+	pub name: &'static str,
+	pub enabled: bool,
+	pub run: fn(&mut ASTNode) -> bool,
+}
+
+//How many times the pass list gets rerun before giving up on reaching a fixed point
+const MAX_ROUNDS: usize = 16;
+
+//Passes can be turned off without recompiling by listing their names in GEL_DISABLED_PASSES,
+//e.g. GEL_DISABLED_PASSES=constant_folding,dead_branch_elimination
+pub fn ast_passes() -> Vec<AstPass> {
+	let mut passes = vec![
+		AstPass{name: "constant_folding", enabled: true, run: constant_folding},
+		AstPass{name: "dead_branch_elimination", enabled: true, run: dead_branch_elimination},
+		AstPass{name: "algebraic_simplification", enabled: true, run: algebraic_simplification},
+	];
+	if let Ok(disabled) = std::env::var("GEL_DISABLED_PASSES") {
+		for pass in &mut passes {
+			pass.enabled = !disabled.split(',').any(|name| name.trim() == pass.name);
+		}
+	}
+	return passes;
+}
 
-pub fn optimize_ast(ast: ASTNode/*, optimizers: Vec<&dyn FnMut(ASTNode) -> ASTNode>*/) -> ASTNode {
+pub fn optimize_ast(ast: ASTNode) -> ASTNode {
+	return optimize_ast_with(ast, &ast_passes());
+}
+
+//Passes open up work for each other (x * 1 == 1 only folds once the * 1 is gone), so keep going until nothing changes
+pub fn optimize_ast_with(mut ast: ASTNode, passes: &[AstPass]) -> ASTNode {
+	for _ in 0..MAX_ROUNDS {
+		let mut changed = false;
+		for pass in passes {
+			if pass.enabled {
+				changed |= (pass.run)(&mut ast);
+			}
+		}
+		if !changed {
+			break;
+		}
+	}
 	return ast;
-}
\ No newline at end of file
+}
+
+//Replaces operator chains, parenthesized expressions and negations that only use literals with the literal they evaluate to.
+//e.g. 2**2**3 becomes 256, (7 > 1) becomes 1 and -(2) becomes -2
+pub fn constant_folding(ast: &mut ASTNode) -> bool {
+	let mut changed = false;
+	for child in &mut ast.children {
+		changed |= constant_folding(child);
+	}
+	match ast.rule.as_str() {
+		"OpPrec1" | "OpPrec2" | "OpPrec3" | "OpPrec4" => {
+			if ast.children.len() == 2 && !is_empty(&ast.children[1]) {
+				if let Some(unit) = const_value(ast).and_then(|value| literal_unit(value, ast.line)) {
+					ast.children[0] = unit;
+					ast.children[1].children = vec![none_node(ast.line)];
+					changed = true;
+				}
+			}
+		}
+		"Unit" => {
+			if ast.children.len() == 4 || ast.children[0].rule == "MINUS" {
+				if let Some(unit) = const_value(ast).and_then(|value| literal_unit(value, ast.line)) {
+					*ast = unit;
+					changed = true;
+				}
+			}
+		}
+		_ => {}
+	}
+	return changed;
+}
+
+//if(true)/if(false) with a literal condition gets replaced by the branch that would run
+pub fn dead_branch_elimination(ast: &mut ASTNode) -> bool {
+	let mut changed = false;
+	for child in &mut ast.children {
+		changed |= dead_branch_elimination(child);
+	}
+	if ast.rule == "Stat" && ast.children.len() == 1 && ast.children[0].rule == "If" && ast.children[0].children.len() == 4 {
+		if let Some(condition) = const_value(&ast.children[0].children[1]) {
+			let mut if_node = ast.children.remove(0);
+			if is_truthy(&condition) {
+				ast.children.push(if_node.children.remove(2));
+			} else if if_node.children[3].children.len() > 1 {
+				ast.children.push(if_node.children[3].children.remove(1));
+			} else {
+				ast.children.push(none_node(if_node.line));
+			}
+			changed = true;
+		}
+	}
+	return changed;
+}
+
+//Drops operations that can't change their operand: x * 1, 1 * x, x / 1, x - 0, and a trailing ** 1.
+//Only when x is known to be a number though, since on a string (or an Int next to a Decimal) they're errors or Null instead.
+//x + 0 is left alone since + also works on strings, where it isn't a no-op.
+pub fn algebraic_simplification(ast: &mut ASTNode) -> bool {
+	let mut changed = false;
+	for child in &mut ast.children {
+		changed |= algebraic_simplification(child);
+	}
+	if ast.children.len() != 2 {
+		return changed;
+	}
+	match ast.rule.as_str() {
+		"OpPrec2" => {
+			//1 * x
+			if is_literal(&ast.children[0], 1) && step_op(&ast.children[1]) == Some("MULT") && is_numeric(&ast.children[1].children[1]) {
+				let mut tail = ast.children.remove(1);
+				let rest = tail.children.remove(2);
+				ast.children[0] = tail.children.remove(1);
+				ast.children.push(rest);
+				changed = true;
+			}
+			let numeric = is_numeric(&ast.children[0]);
+			changed |= remove_steps(&mut ast.children[1], numeric, &|op, operand| (op == "MULT" || op == "DIV") && is_literal(operand, 1));
+		}
+		"OpPrec3" => {
+			let numeric = is_numeric(&ast.children[0]);
+			changed |= remove_steps(&mut ast.children[1], numeric, &|op, operand| op == "MINUS" && is_literal(operand, 0));
+		}
+		"OpPrec1" => {
+			//exponents are right associative, so only the last ** 1 can go, and what it's on is the operand before it
+			let (first, tail) = ast.children.split_at_mut(1);
+			let mut base = &first[0];
+			let mut tail = &mut tail[0];
+			while !is_empty(tail) && !is_empty(&tail.children[2]) {
+				let (operand, rest) = tail.children.split_at_mut(2);
+				base = &operand[1];
+				tail = &mut rest[0];
+			}
+			if step_op(tail) == Some("EXP") && is_literal(&tail.children[1], 1) && is_numeric(base) {
+				tail.children = vec![none_node(tail.line)];
+				changed = true;
+			}
+		}
+		_ => {}
+	}
+	return changed;
+}
+
+//Removes every step of a left associative chain that matches, e.g. the "* 1" out of "x * 1 * y",
+//as long as the value going into the step is known to be a number (numeric says whether the first one is)
+fn remove_steps(tail: &mut ASTNode, numeric: bool, matches: &dyn Fn(&str, &ASTNode) -> bool) -> bool {
+	let mut changed = false;
+	while let Some(op) = step_op(tail) {
+		if numeric && matches(op, &tail.children[1]) {
+			let rest = tail.children.remove(2);
+			*tail = rest;
+			changed = true;
+		} else {
+			break;
+		}
+	}
+	if let Some(op) = step_op(tail) {
+		let numeric = ARITHMETIC.contains(&op);
+		changed |= remove_steps(&mut tail.children[2], numeric, matches);
+	}
+	return changed;
+}
+
+//Operators that only ever give back a number (or an error), unlike + which joins strings and the comparisons which give Null for them
+const ARITHMETIC: [&str; 4] = ["MINUS", "MULT", "DIV", "EXP"];
+
+//Whether node always evaluates to a number: a numeric literal, a negation, or a chain of arithmetic
+fn is_numeric(node: &ASTNode) -> bool {
+	if let Some(Data::Decimal(_)) | Some(Data::Int(_)) = const_value(node) {
+		return true;
+	}
+	match node.rule.as_str() {
+		"Expr" => {
+			return node.children.first().map_or(false, is_numeric);
+		}
+		"OpPrec1" | "OpPrec2" | "OpPrec3" | "OpPrec4" | "OpPrec5" => {
+			if node.children.len() != 2 {
+				return false;
+			}
+			if is_empty(&node.children[1]) {
+				return is_numeric(&node.children[0]);
+			}
+			let mut tail = &node.children[1];
+			while let Some(op) = step_op(tail) {
+				if !ARITHMETIC.contains(&op) {
+					return false;
+				}
+				tail = &tail.children[2];
+			}
+			return true;
+		}
+		"Unit" => {
+			if node.children.len() == 4 && is_empty(&node.children[3]) {
+				return is_numeric(&node.children[1]);
+			}
+			return node.children.len() == 2 && node.children[0].rule == "MINUS";
+		}
+		_ => {
+			return false;
+		}
+	}
+}
+
+//The operator token at the front of a chain tail like OpMD ::= MULT OpPrec1 OpMD, if there is one
+fn step_op(tail: &ASTNode) -> Option<&str> {
+	if tail.children.len() == 3 {
+		return Some(tail.children[0].rule.as_str());
+	}
+	return None;
+}
+
+//An empty chain tail or type hint (only NONE children)
+fn is_empty(node: &ASTNode) -> bool {
+	return node.children.iter().all(|child| child.rule == "NONE");
+}
+
+fn is_literal(node: &ASTNode, value: i32) -> bool {
+	return const_value(node) == Some(Data::Decimal(Decimal::from(value)));
+}
+
+//Same rules CHECK_IF_NOT uses
+fn is_truthy(data: &Data) -> bool {
+	match data {
+		Data::Null => return false,
+		Data::Int(i) => return *i != 0,
+		Data::Decimal(i) => return *i != Decimal::from(0),
+		_ => return true,
+	}
+}
+
+//The value a piece of the AST always evaluates to, if it only uses literals
+pub fn const_value(node: &ASTNode) -> Option<Data> {
+	match node.rule.as_str() {
+		"INT" | "DECIMAL" => {
+			return Decimal::from_str(node.data.as_ref()?.1.as_str()).ok().map(Data::Decimal);
+		}
+		"STRING" => {
+			let text = &node.data.as_ref()?.1;
+			return Some(Data::String(text[1..text.len() - 1].to_string()));
+		}
+		"Val" | "Expr" => {
+			return const_value(node.children.first()?);
+		}
+		"Unit" => {
+			if node.children.len() == 4 && is_empty(&node.children[3]) {
+				return const_value(&node.children[1]);
+			} else if node.children.len() == 2 && node.children[0].rule == "Val" && is_empty(&node.children[1]) {
+				return const_value(&node.children[0]);
+			} else if node.children.len() == 2 && node.children[0].rule == "MINUS" {
+				return fold_op(Data::Decimal(Decimal::from(0)), const_value(&node.children[1])?, "MINUS");
+			}
+			return None;
+		}
+		"OpPrec5" => {
+			if node.children.len() == 2 && is_empty(&node.children[1]) {
+				return const_value(&node.children[0]);
+			}
+			return None;
+		}
+		"OpPrec1" | "OpPrec2" | "OpPrec3" | "OpPrec4" => {
+			if node.children.len() != 2 {
+				return None;
+			}
+			let first = const_value(&node.children[0])?;
+			let mut steps = vec![];
+			let mut tail = &node.children[1];
+			while let Some(op) = step_op(tail) {
+				steps.push((op, const_value(&tail.children[1])?));
+				tail = &tail.children[2];
+			}
+			if node.rule == "OpPrec1" {
+				//right associative, so fold from the end
+				let mut operands = vec![first];
+				operands.extend(steps.iter().map(|step| step.1.clone()));
+				let mut value = operands.pop()?;
+				for (i, operand) in operands.into_iter().enumerate().rev() {
+					value = fold_op(operand, value, steps[i].0)?;
+				}
+				return Some(value);
+			}
+			let mut value = first;
+			for (op, operand) in steps {
+				value = fold_op(value, operand, op)?;
+			}
+			return Some(value);
+		}
+		_ => {
+			return None;
+		}
+	}
+}
+
+//Runs the operator the same way the interpreter would, refusing anything it can't do (or that would panic)
+//Anything that would overflow or divide by 0 isn't folded, so the interpreter reports it when the code runs instead of the compiler crashing
+fn fold_op(left: Data, right: Data, op: &str) -> Option<Data> {
+	match (&left, &right, op) {
+		(Data::Decimal(l), Data::Decimal(r), "PLUS") => return l.checked_add(*r).map(Data::Decimal),
+		(Data::Decimal(l), Data::Decimal(r), "MINUS") => return l.checked_sub(*r).map(Data::Decimal),
+		(Data::Decimal(l), Data::Decimal(r), "MULT") => return l.checked_mul(*r).map(Data::Decimal),
+		(Data::Decimal(l), Data::Decimal(r), "DIV") => return l.checked_div(*r).map(Data::Decimal),
+		(Data::Int(l), Data::Int(r), "PLUS") => return l.checked_add(*r).map(Data::Int),
+		(Data::Int(l), Data::Int(r), "MINUS") => return l.checked_sub(*r).map(Data::Int),
+		(Data::Int(l), Data::Int(r), "MULT") => return l.checked_mul(*r).map(Data::Int),
+		(Data::Int(l), Data::Int(r), "DIV") => return l.checked_div(*r).map(Data::Int),
+		(Data::Int(l), Data::Int(r), "EXP") => return u32::try_from(*r).ok().and_then(|r| l.checked_pow(r)).map(Data::Int),
+		_ => {}
+	}
+	match data_operation(left, right, op.to_string()) {
+		Ok(Data::Null) | Err(_) => return None,
+		Ok(value) => return Some(value),
+	}
+}
+
+fn none_node(line: i32) -> ASTNode {
+	return ASTNode{rule: "NONE".to_string(), data: None, children: vec![], line};
+}
+
+//Unit ::= Val TypeHint, with the value turned back into the token the scanner would have made.
+//Values that aren't written as a single literal (colors, objects...) are left unfolded.
+fn literal_unit(value: Data, line: i32) -> Option<ASTNode> {
+	let token = match value {
+		Data::Decimal(value) => ("DECIMAL".to_string(), value.normalize().to_string(), line),
+		Data::Int(value) => ("INT".to_string(), value.to_string(), line),
+		Data::String(value) => ("STRING".to_string(), format!("\"{}\"", value), line),
+		_ => return None,
+	};
+	let leaf = ASTNode{rule: token.0.to_owned(), data: Some(token), children: vec![], line};
+	let val = ASTNode{rule: "Val".to_string(), data: None, children: vec![leaf], line};
+	let hint = ASTNode{rule: "TypeHint".to_string(), data: None, children: vec![none_node(line)], line};
+	return Some(ASTNode{rule: "Unit".to_string(), data: None, children: vec![val, hint], line});
+}
+
+#[cfg(test)]
+fn compile_with(input: &str, passes: &[AstPass]) -> crate::datatypes::Program {
+	let ast = crate::parser::parser_setup::parse(input.to_string()).unwrap();
+	return crate::parser::linearize_ast::linearize_ast(&mut optimize_ast_with(ast, passes)).unwrap();
+}
+
+#[cfg(test)]
+fn root_instructions(program: &crate::datatypes::Program) -> Vec<String> {
+	return program.functions[""].opcodes.iter().map(|op| op.instruction.to_owned()).collect();
+}
+
+#[test]
+fn constant_folding_test(){
+	let mut program = compile_with("print(2**2**3); print((7 > 1) + 2 * 3)", &ast_passes());
+	let instructions = root_instructions(&program);
+	assert!(!instructions.contains(&"EXP".to_string()) && !instructions.contains(&"PLUS".to_string()));
+	assert!(program.functions[""].opcodes.iter().any(|op| op.data == Data::Decimal(Decimal::from(256))));
+	crate::interpreter::interpreter::interpret_program(&mut program, "").unwrap();
+	assert!(program.log == vec!["256", "7"]);
+
+	//turning the pass off leaves the operators in
+	let mut passes = ast_passes();
+	passes.iter_mut().for_each(|pass| pass.enabled = pass.name != "constant_folding");
+	assert!(root_instructions(&compile_with("print(2**2**3)", &passes)).contains(&"EXP".to_string()));
+}
+
+#[test]
+fn constant_folding_overflow_test(){
+	//folds that would overflow are left for the interpreter instead of crashing the compiler
+	let program = compile_with("if(false) print(79228162514264337593543950335 * 2)\nprint(1e20 * 1e20)", &ast_passes());
+	assert!(root_instructions(&program).contains(&"MULT".to_string()));
+}
+
+#[test]
+fn dead_branch_elimination_test(){
+	let mut program = compile_with("if(false) print(\"no\") else print(\"yes\")\nif(1 == 1) print(\"also yes\")\nif(0) print(\"no\")", &ast_passes());
+	let instructions = root_instructions(&program);
+	assert!(!instructions.contains(&"CHECK_IF_NOT".to_string()) && !instructions.contains(&"CHECK_ELSE_NOT".to_string()));
+	crate::interpreter::interpreter::interpret_program(&mut program, "").unwrap();
+	assert!(program.log == vec!["yes", "also yes"]);
+}
+
+#[test]
+fn algebraic_simplification_test(){
+	let mut program = compile_with("var x = 5\nprint(1 * (x - 1) * 1 / 1 - 0)\nprint((x - 1) ** 1)", &ast_passes());
+	let instructions = root_instructions(&program);
+	for op in ["MULT", "DIV", "EXP"] {
+		assert!(!instructions.contains(&op.to_string()));
+	}
+	assert!(instructions.iter().filter(|op| *op == "MINUS").count() == 2);
+	crate::interpreter::interpreter::interpret_program(&mut program, "").unwrap();
+	assert!(program.log == vec!["4", "4"]);
+
+	//x could be a string, where these aren't no-ops, so they stay
+	let instructions = root_instructions(&compile_with("var x = \"a\"\nprint(x * 1)\nprint(x - 0)\nprint((x + 1) / 1)\nprint(x ** 1)", &ast_passes()));
+	for op in ["MULT", "DIV", "MINUS", "EXP"] {
+		assert!(instructions.contains(&op.to_string()));
+	}
+}
+
+#[test]
+fn optimizer_keeps_results(){
+	let input = std::fs::read_to_string("test_files/test2.gel").unwrap();
+	let mut optimized = compile_with(&input, &ast_passes());
+	let mut unoptimized = compile_with(&input, &[]);
+	crate::interpreter::interpreter::interpret_program(&mut optimized, "").unwrap();
+	crate::interpreter::interpreter::interpret_program(&mut unoptimized, "").unwrap();
+	assert!(optimized.log == unoptimized.log);
+	assert!(optimized.functions[""].opcodes.len() < unoptimized.functions[""].opcodes.len());
+}
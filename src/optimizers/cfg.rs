@@ -0,0 +1,157 @@
+use rust_decimal::prelude::*;
+use crate::datatypes::*;
+
+//What a single opcode does to control flow
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Jump {
+	//Not a jump, runs the next opcode
+	None,
+	//Jumps to the label every time
+	Always(usize),
+	//Jumps to the label depending on a value only known at runtime
+	Maybe(usize),
+	//A jump whose condition is a constant that never jumps
+	Never(usize),
+	//Leaves the function
+	Exit,
+}
+
+#[derive(Debug)]
+pub struct BasicBlock {
+	//opcodes[start..end] are in the block
+	pub start: usize,
+	pub end: usize,
+	pub successors: Vec<usize>,
+	pub predecessors: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct ControlFlowGraph {
+	pub blocks: Vec<BasicBlock>,
+}
+
+pub fn jump_kind(op: &Opcode) -> Jump {
+	let label = match op.data2 {
+		Data::Label(label) => label,
+		_ => {
+			//both leave the function, so nothing runs after them
+			if op.instruction == "END_FUNC" || op.instruction == "RETURN" {
+				return Jump::Exit;
+			}
+			return Jump::None;
+		}
+	};
+	match op.instruction.as_str() {
+		//both jump when their value is falsy
+		"CHECK_IF_NOT" | "CHECK_ELSE_NOT" => {
+			match constant_truth(&op.data) {
+				Some(false) => return Jump::Always(label),
+				Some(true) => return Jump::Never(label),
+				None => return Jump::Maybe(label),
+			}
+		}
+		//jumps back to the top of the loop when its value is truthy
+		"FOR_GOTO" => {
+			match constant_truth(&op.data) {
+				Some(true) => return Jump::Always(label),
+				Some(false) => return Jump::Never(label),
+				None => return Jump::Maybe(label),
+			}
+		}
+		//with goes past its block when there are no instances, and its WITH_NEXT goes back to the top for every instance after the first
+		"WITH" | "WITH_NEXT" => {
+			return Jump::Maybe(label);
+		}
+		_ => return Jump::None,
+	}
+}
+
+//Whether a jump's value is known to be truthy or falsy without running anything, following the interpreter's rules
+pub fn constant_truth(data: &Data) -> Option<bool> {
+	match data {
+		Data::Null => return Some(false),
+		Data::Int(i) => return Some(*i != 0),
+		Data::Decimal(i) => return Some(*i != Decimal::from(0)),
+		Data::String(_) | Data::Color(..) | Data::Type(_) => return Some(true),
+		_ => return None,
+	}
+}
+
+pub fn build_cfg(function: &Function) -> ControlFlowGraph {
+	let opcodes = &function.opcodes;
+	//Blocks start at the top, at every label, and after every jump
+	let mut leaders = vec![false; opcodes.len() + 1];
+	leaders[0] = true;
+	for position in &function.labels {
+		if *position < leaders.len() {
+			leaders[*position] = true;
+		}
+	}
+	for (index, op) in opcodes.iter().enumerate() {
+		if jump_kind(op) != Jump::None {
+			leaders[index + 1] = true;
+		}
+	}
+
+	let mut blocks = vec![];
+	//Which block each opcode is in
+	let mut block_of = vec![0; opcodes.len()];
+	let mut start = 0;
+	for index in 1..=opcodes.len() {
+		if leaders[index] || index == opcodes.len() {
+			if start < index {
+				for op_index in start..index {
+					block_of[op_index] = blocks.len();
+				}
+				blocks.push(BasicBlock{start, end: index, successors: vec![], predecessors: vec![]});
+			}
+			start = index;
+		}
+	}
+
+	//A jump to opcodes.len() (the end of the function) has no block to go to, it just returns
+	for block in 0..blocks.len() {
+		let last = &opcodes[blocks[block].end - 1];
+		let next = if blocks[block].end < opcodes.len() { Some(block_of[blocks[block].end]) } else { None };
+		let target = |label: usize| function.labels.get(label).filter(|position| **position < opcodes.len()).map(|position| block_of[*position]);
+		let mut successors = vec![];
+		match jump_kind(last) {
+			Jump::Always(label) => {
+				successors.extend(target(label));
+			}
+			Jump::Maybe(label) => {
+				successors.extend(target(label));
+				successors.extend(next);
+			}
+			Jump::Exit => {}
+			Jump::None | Jump::Never(_) => {
+				successors.extend(next);
+			}
+		}
+		successors.dedup();
+		for successor in &successors {
+			blocks[*successor].predecessors.push(block);
+		}
+		blocks[block].successors = successors;
+	}
+	return ControlFlowGraph{blocks};
+}
+
+//Blocks that can be reached from the start of the function
+pub fn reachable_blocks(cfg: &ControlFlowGraph) -> Vec<bool> {
+	let mut reachable = vec![false; cfg.blocks.len()];
+	let mut stack = vec![];
+	if cfg.blocks.len() > 0 {
+		stack.push(0);
+	}
+	while let Some(block) = stack.pop() {
+		if reachable[block] {
+			continue;
+		}
+		reachable[block] = true;
+		for successor in &cfg.blocks[block].successors {
+			stack.push(*successor);
+		}
+	}
+	return reachable;
+}
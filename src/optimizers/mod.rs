@@ -1 +1,3 @@
-pub mod ast_optimizer;
\ No newline at end of file
+pub mod ast_optimizer;
+pub mod cfg;
+pub mod opcode_optimizer;
\ No newline at end of file
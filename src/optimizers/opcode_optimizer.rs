@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::datatypes::*;
+use crate::optimizers::cfg::*;
+
+//A single optimization over one function's opcodes, returning whether it changed anything.
+//Same idea as AstPass, and GEL_DISABLED_PASSES turns these off by name too.
+pub struct OpcodePass {
+	pub name: &'static str,
+	pub enabled: bool,
+	pub run: fn(&mut Function) -> bool,
+}
+
+const MAX_ROUNDS: usize = 16;
+
+//Instructions that read their operands through get_value, so a register can be swapped for what it holds.
+//Set is left out since it needs its value to stay a register.
+const VALUE_READERS: &[&str] = &["FUNC", "Comma", "INCR", "CHECK_IF_NOT", "CHECK_ELSE_NOT", "FOR_GOTO", "PLUS", "MINUS", "MULT", "DIV", "EXP", "GT", "LT", "EQ", "AND", "OR", "DOT", "WITH", "ARG", "RETURN"];
+
+pub fn opcode_passes() -> Vec<OpcodePass> {
+	let mut passes = vec![
+		OpcodePass{name: "peephole", enabled: true, run: peephole},
+		OpcodePass{name: "copy_propagation", enabled: true, run: copy_propagation},
+		OpcodePass{name: "jump_threading", enabled: true, run: jump_threading},
+		OpcodePass{name: "unreachable_blocks", enabled: true, run: unreachable_blocks},
+		OpcodePass{name: "dead_code", enabled: true, run: dead_code},
+	];
+	if let Ok(disabled) = std::env::var("GEL_DISABLED_PASSES") {
+		for pass in &mut passes {
+			pass.enabled = !disabled.split(',').any(|name| name.trim() == pass.name);
+		}
+	}
+	return passes;
+}
+
+pub fn optimize_opcodes(program: &mut Program) {
+	optimize_opcodes_with(program, &opcode_passes());
+}
+
+pub fn optimize_opcodes_with(program: &mut Program, passes: &[OpcodePass]) {
+	for function in program.functions.values_mut() {
+		//Functions that are already shared (running, or swapped in elsewhere) are left as they are
+		if let Some(function) = Rc::get_mut(function) {
+			optimize_function(function, passes);
+		}
+	}
+}
+
+pub fn optimize_function(function: &mut Function, passes: &[OpcodePass]) {
+	for _ in 0..MAX_ROUNDS {
+		let mut changed = false;
+		for pass in passes {
+			if pass.enabled {
+				changed |= (pass.run)(function);
+			}
+		}
+		if !changed {
+			break;
+		}
+	}
+}
+
+//Cleans up patterns linearize leaves behind:
+//	leftover NOP and FLAG markers (FLAG only means something while linearize is patching operators)
+//	a Comma holding a single argument, which FUNC can take directly
+pub fn peephole(function: &mut Function) -> bool {
+	let mut remove = vec![false; function.opcodes.len()];
+	for (index, op) in function.opcodes.iter().enumerate() {
+		if op.instruction == "NOP" || op.instruction == "FLAG" {
+			remove[index] = true;
+		}
+	}
+	let uses = register_uses(function);
+	let mut single_args: HashMap<u32, Data> = HashMap::new();
+	for (index, op) in function.opcodes.iter().enumerate() {
+		if op.instruction == "Comma" && op.data2 == Data::Null && op.register != 0 && uses.get(&op.register) == Some(&1) {
+			single_args.insert(op.register, op.data.clone());
+			remove[index] = true;
+		}
+	}
+	let mut changed = false;
+	for op in &mut function.opcodes {
+		if op.instruction == "FUNC" {
+			if let Data::Register(reg) = op.data2 {
+				if let Some(arg) = single_args.remove(&reg) {
+					op.data2 = arg;
+					changed = true;
+				}
+			}
+		}
+	}
+	//Commas that weren't used by a FUNC stay
+	for (index, op) in function.opcodes.iter().enumerate() {
+		if op.instruction == "Comma" && single_args.contains_key(&op.register) {
+			remove[index] = false;
+		}
+	}
+	return remove_opcodes(function, &remove) || changed;
+}
+
+//Value and ID only ever put the same constant into their register, so anything reading that register can read the constant instead
+pub fn copy_propagation(function: &mut Function) -> bool {
+	let mut writers: HashMap<u32, usize> = HashMap::new();
+	for op in &function.opcodes {
+		if op.register != 0 {
+			*writers.entry(op.register).or_insert(0) += 1;
+		}
+	}
+	let mut constants: HashMap<u32, Data> = HashMap::new();
+	for op in &function.opcodes {
+		if (op.instruction == "Value" || op.instruction == "ID") && writers.get(&op.register) == Some(&1) {
+			constants.insert(op.register, op.data.clone());
+		}
+	}
+	let mut changed = false;
+	for op in &mut function.opcodes {
+		if VALUE_READERS.contains(&op.instruction.as_str()) {
+			changed |= replace_registers(&mut op.data, &constants);
+			changed |= replace_registers(&mut op.data2, &constants);
+		}
+	}
+	return changed;
+}
+
+fn replace_registers(data: &mut Data, constants: &HashMap<u32, Data>) -> bool {
+	match data {
+		Data::Register(reg) => {
+			if let Some(constant) = constants.get(reg) {
+				*data = constant.clone();
+				return true;
+			}
+			return false;
+		}
+		Data::Comma(left, right) => {
+			let left_changed = replace_registers(left, constants);
+			return replace_registers(right, constants) || left_changed;
+		}
+		_ => {
+			return false;
+		}
+	}
+}
+
+//Jumps that land on another jump that always goes somewhere go straight there instead,
+//jumps with constant conditions become plain jumps (or disappear), and jumps to the very next opcode are dropped
+pub fn jump_threading(function: &mut Function) -> bool {
+	let mut changed = false;
+	let mut remove = vec![false; function.opcodes.len()];
+	for index in 0..function.opcodes.len() {
+		match jump_kind(&function.opcodes[index]) {
+			Jump::Always(label) => {
+				let label = final_label(function, label);
+				let op = &mut function.opcodes[index];
+				if op.instruction != "CHECK_ELSE_NOT" || op.data != Data::Null || op.data2 != Data::Label(label) {
+					op.instruction = "CHECK_ELSE_NOT".to_string();
+					op.data = Data::Null;
+					op.data2 = Data::Label(label);
+					changed = true;
+				}
+				if function.labels[label] == index + 1 {
+					remove[index] = true;
+				}
+			}
+			Jump::Maybe(label) => {
+				let label = final_label(function, label);
+				if function.opcodes[index].data2 != Data::Label(label) {
+					function.opcodes[index].data2 = Data::Label(label);
+					changed = true;
+				}
+				if function.labels[label] == index + 1 {
+					remove[index] = true;
+				}
+			}
+			Jump::Never(_) => {
+				remove[index] = true;
+			}
+			Jump::None | Jump::Exit => {}
+		}
+	}
+	return remove_opcodes(function, &remove) || changed;
+}
+
+//Where a jump to label really ends up, following any unconditional jumps it lands on (and giving up on loops of them)
+fn final_label(function: &Function, mut label: usize) -> usize {
+	let mut hops = 0;
+	while let Some(Jump::Always(next)) = function.labels.get(label).and_then(|position| function.opcodes.get(*position)).map(jump_kind) {
+		if next == label || hops > function.opcodes.len() {
+			break;
+		}
+		label = next;
+		hops += 1;
+	}
+	return label;
+}
+
+//Drops basic blocks that nothing can reach
+pub fn unreachable_blocks(function: &mut Function) -> bool {
+	let cfg = build_cfg(function);
+	let reachable = reachable_blocks(&cfg);
+	let mut remove = vec![false; function.opcodes.len()];
+	for (block, info) in cfg.blocks.iter().enumerate() {
+		if !reachable[block] {
+			for index in info.start..info.end {
+				remove[index] = true;
+			}
+		}
+	}
+	return remove_opcodes(function, &remove);
+}
+
+//Drops Value, ID and Comma opcodes whose register nothing reads
+pub fn dead_code(function: &mut Function) -> bool {
+	let uses = register_uses(function);
+	let mut remove = vec![false; function.opcodes.len()];
+	for (index, op) in function.opcodes.iter().enumerate() {
+		if (op.instruction == "Value" || op.instruction == "ID" || op.instruction == "Comma") && !uses.contains_key(&op.register) {
+			remove[index] = true;
+		}
+	}
+	return remove_opcodes(function, &remove);
+}
+
+//How many times each register gets read
+fn register_uses(function: &Function) -> HashMap<u32, usize> {
+	let mut uses = HashMap::new();
+	for op in &function.opcodes {
+		count_registers(&op.data, &mut uses);
+		count_registers(&op.data2, &mut uses);
+	}
+	return uses;
+}
+
+fn count_registers(data: &Data, uses: &mut HashMap<u32, usize>) {
+	match data {
+		Data::Register(reg) => {
+			*uses.entry(*reg).or_insert(0) += 1;
+		}
+		Data::Comma(left, right) => {
+			count_registers(left, uses);
+			count_registers(right, uses);
+		}
+		_ => {}
+	}
+}
+
+//Removes the marked opcodes, moving labels so they still point at the same code
+fn remove_opcodes(function: &mut Function, remove: &[bool]) -> bool {
+	if !remove.contains(&true) {
+		return false;
+	}
+	let mut new_position = vec![0; remove.len() + 1];
+	let mut kept = 0;
+	for index in 0..remove.len() {
+		new_position[index] = kept;
+		if !remove[index] {
+			kept += 1;
+		}
+	}
+	new_position[remove.len()] = kept;
+	for label in &mut function.labels {
+		*label = new_position[(*label).min(remove.len())];
+	}
+	let mut index = 0;
+	function.opcodes.retain(|_| {
+		index += 1;
+		return !remove[index - 1];
+	});
+	return true;
+}
+
+#[cfg(test)]
+fn compile_unoptimized(input: &str) -> Program {
+	let ast = crate::parser::parser_setup::parse(input.to_string()).unwrap();
+	return crate::parser::linearize_ast::linearize_ast(&mut crate::optimizers::ast_optimizer::optimize_ast_with(ast, &[])).unwrap();
+}
+
+#[cfg(test)]
+fn run_all(program: &mut Program) -> Vec<String> {
+	crate::interpreter::interpreter::interpret_program(program, "").unwrap();
+	crate::interpreter::interpreter::interpret_program(program, "init").unwrap();
+	return program.log.to_owned();
+}
+
+#[test]
+fn opcode_optimizer_removes_dead_branches(){
+	let mut program = compile_unoptimized("if(false) print(\"no\") else print(\"yes\")");
+	optimize_opcodes(&mut program);
+	//everything but the one print is gone: the condition got propagated, the jump became unconditional,
+	//the then branch became unreachable, and the jumps left pointing at the next opcode were dropped
+	let instructions: Vec<&str> = program.functions[""].opcodes.iter().map(|op| op.instruction.as_str()).collect();
+	assert!(instructions == vec!["FUNC"]);
+	assert!(run_all(&mut program) == vec!["yes"]);
+}
+
+#[test]
+fn opcode_optimizer_threads_jumps(){
+	let mut program = compile_unoptimized("#define init{\n\tvar a = 1\n\tif(a) { if(a) print(\"a\") } else print(\"b\")\n\tprint(\"c\")\n}");
+	optimize_opcodes(&mut program);
+	let function = &program.functions["init"];
+	for op in &function.opcodes {
+		if let Jump::Always(label) | Jump::Maybe(label) = jump_kind(op) {
+			let target = function.opcodes.get(function.labels[label]);
+			assert!(target.map(jump_kind).filter(|jump| matches!(jump, Jump::Always(_))).is_none());
+		}
+	}
+	assert!(run_all(&mut program) == vec!["a", "c"]);
+}
+
+#[test]
+fn opcode_optimizer_keeps_results(){
+	for file in ["test_files/test2.gel", "test_files/unittest1.gel", "test_files/unittest3.gel", "test_files/TestProgram.gel"] {
+		let input = std::fs::read_to_string(file).unwrap();
+		let mut optimized = compile_unoptimized(&input);
+		let mut unoptimized = compile_unoptimized(&input);
+		optimize_opcodes(&mut optimized);
+		let size = |program: &Program| program.functions.values().map(|function| function.opcodes.len()).sum::<usize>();
+		assert!(size(&optimized) < size(&unoptimized));
+		assert!(run_all(&mut optimized) == run_all(&mut unoptimized));
+	}
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+//The tables the scanner and parser run from.
+//build.rs makes GEL's ahead of time from dialects/gel.grammar and dialects/gel.tokens (see grammar_generator),
+//and it includes this file to do that, so nothing else from the crate can be used in here.
+
+#[derive(Clone, Copy, Debug)]
+pub enum TokenAction {
+	Keywords,
+	Identity,
+	Bool,
+	HexNum,
+	BinNum,
+	Number,
+	String,
+	Newline,
+	Comment,
+	Whitespace,
+}
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct GrammarToken {
+	pub is_terminal: bool,
+	pub value: String,
+	pub is_subrule: bool
+}
+
+//Options of a rule that could all be followed when one of the tokens comes next
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+	pub rule: String,
+	pub options: Vec<usize>,
+	pub tokens: Vec<String>,
+}
+
+//What the Top-Down Parser works from: the rules after the generator is done rewriting them,
+//and for every rule, which of its options to follow for each token that can come next (an LL(1) parse table)
+#[derive(Clone)]
+pub struct ParseTable {
+	pub rules: HashMap<String, Vec<Vec<GrammarToken>>>,
+	pub table: HashMap<String, HashMap<String, usize>>,
+	//Conflicts that were settled by taking the option that starts with the token,
+	//like an else going to the closest if instead of ending it early (empty for the tables build.rs writes out, which lists them in a comment instead)
+	#[allow(dead_code)]
+	pub resolved: Vec<Conflict>,
+}
+
+impl ParseTable {
+	pub fn predict(&self, rule: &str, token: &str) -> Option<&Vec<GrammarToken>> {
+		let option = self.table.get(rule)?.get(token)?;
+		return self.rules[rule].get(*option);
+	}
+
+	//Builds a ParseTable back up from the static tables build.rs writes out.
+	//Rules are (name, options), with every token as (value, is_terminal, is_subrule), and the table is (rule, [(token, option)])
+	pub fn from_static(rules: &[(&str, &[&[(&str, bool, bool)]])], table: &[(&str, &[(&str, usize)])]) -> ParseTable {
+		let mut ret_val = ParseTable{rules: HashMap::new(), table: HashMap::new(), resolved: vec![]};
+		for (name, options) in rules {
+			let options = options.iter().map(|option| option.iter().map(|tok| GrammarToken{is_terminal: tok.1, value: tok.0.to_string(), is_subrule: tok.2}).collect()).collect();
+			ret_val.rules.insert(name.to_string(), options);
+		}
+		for (name, row) in table {
+			ret_val.table.insert(name.to_string(), row.iter().map(|(token, option)| (token.to_string(), *option)).collect());
+		}
+		return ret_val;
+	}
+}
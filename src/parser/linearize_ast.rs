@@ -1,100 +1,199 @@
 use std::collections::HashMap;
+use std::rc::Rc;
 use crate::datatypes::*;
 use rust_decimal::prelude::*;
 
-pub fn linearize_ast(ast: &mut ASTNode) -> Program {
+//Fails on literals that can't be turned into values, like a number too big for a Decimal
+pub fn linearize_ast(ast: &mut ASTNode) -> Result<Program, String> {
 	let mut program = Program::new();
-	let ops = linearize(ast, &mut 1, 1, &mut program);
-	program.functions.insert("".to_string(), (FuncData{return_type: Data::Null, input_types: vec![], optional_types: HashMap::new()}, ops));
-	return program;
+	let ops = linearize(ast, &mut 1, &mut 0, &mut program)?;
+	program.functions.insert("".to_string(), Rc::new(resolve_labels(FuncData{return_type: Data::Null, input_types: vec![], optional_types: HashMap::new()}, ops)));
+	return Ok(program);
 }
 
-pub fn linearize(ast: &mut ASTNode, curr_reg: &mut u32, curr_pos: usize, program: &mut Program) -> Vec<Opcode> {
+//Labels are emitted as LABEL opcodes so they move along with the code around them,
+//once a function is finished they get stripped out and turned into that function's label table
+pub fn resolve_labels(data: FuncData, ops: Vec<Opcode>) -> Function {
+	let mut opcodes = vec![];
+	let mut labels = vec![];
+	for op in ops {
+		if op.instruction == "LABEL" {
+			if let Data::Label(label) = op.data {
+				if labels.len() <= label {
+					labels.resize(label + 1, 0);
+				}
+				labels[label] = opcodes.len();
+			}
+		} else {
+			opcodes.push(op);
+		}
+	}
+	return Function{data, opcodes, labels};
+}
+
+//#rrggbb or #rrggbbaa as a Color, with every channel from 0 to 1 like the built in colors
+fn hex_color(text: &str) -> Data {
+	let channel = |index: usize| Decimal::from(u8::from_str_radix(&text[index..index + 2], 16).unwrap()) / Decimal::from(255);
+	let alpha = if text.len() > 7 { channel(7) } else { Decimal::from(1) };
+	return Data::Color(channel(1), channel(3), channel(5), alpha);
+}
+
+//The value of an INT or DECIMAL token, which the scanner should already have stopped at if it doesn't fit in a Decimal
+fn number_literal(ast: &ASTNode) -> Result<Decimal, String> {
+	let text = &ast.data.as_ref().unwrap().1;
+	return Decimal::from_str(text).map_err(|_| format!("The number {} on line {} is too big or isn't a number", text, ast.line));
+}
+
+//The definition after a function without {}, which is the last thing in its Block
+fn take_next_definition(block: &mut ASTNode) -> Option<ASTNode> {
+	match block.children.last().map(|node| node.rule.as_str()) {
+		Some("FuncDef") => {
+			return block.children.pop();
+		}
+		Some("Block") => {
+			return take_next_definition(block.children.last_mut().unwrap());
+		}
+		_ => {
+			return None;
+		}
+	}
+}
+
+fn new_label(label_count: &mut usize) -> Opcode {
+	*label_count += 1;
+	return Opcode{instruction: "LABEL".to_string(), data: Data::Label(*label_count - 1), data2: Data::Null, register: 0, line: 0};
+}
+
+//Arguments get chained together with Comma opcodes after they're all worked out,
+//so (a, b, c) ends up as Comma(a, Comma(b, Comma(c, null)))
+fn linearize_comma_list(args: &mut Vec<&mut ASTNode>, curr_reg: &mut u32, label_count: &mut usize, program: &mut Program) -> Result<Vec<Opcode>, String> {
+	let mut ret_val = vec![];
+	let mut arg_regs = vec![];
+	for arg in args.iter_mut() {
+		let mut child = linearize(arg, curr_reg, label_count, program)?;
+		arg_regs.push((Data::Register(child[child.len() - 1].register), arg.line));
+		ret_val.append(&mut child);
+	}
+	let mut rest = Data::Null;
+	for (reg, line) in arg_regs.into_iter().rev() {
+		ret_val.push(Opcode{instruction: "Comma".to_string(), data: reg, data2: rest, register: *curr_reg, line});
+		rest = Data::Register(*curr_reg);
+		*curr_reg += 1;
+	}
+	return Ok(ret_val);
+}
+
+pub fn linearize(ast: &mut ASTNode, curr_reg: &mut u32, label_count: &mut usize, program: &mut Program) -> Result<Vec<Opcode>, String> {
 	let mut ret_val: Vec<Opcode> = vec![];
 	match ast.rule.as_str(){
 		"Root" => {
 			for i in &mut ast.children {
-				ret_val.append(&mut linearize(i, curr_reg, curr_pos + ret_val.len(), program));
+				ret_val.append(&mut linearize(i, curr_reg, label_count, program)?);
 			}
 		}
 		"For" => {
-			ret_val.append(&mut linearize(&mut ast.children[2], curr_reg, curr_pos + ret_val.len(), program));
+			ret_val.append(&mut linearize(&mut ast.children[2], curr_reg, label_count, program)?);
 			
-			//label to goto
-			/*ret_val.push(Opcode{instruction: "FOR_LABEL".to_string(), data: Data::Label(*curr_reg), data2: Data::Null, register: 0, line: 0});
-			let label_reg = *curr_reg;
-			*curr_reg += 1;*/
+			//check the condition before the first run of the block
+			let checklabel = new_label(label_count);
+			ret_val.push(Opcode{instruction: "CHECK_ELSE_NOT".to_string(), data: Data::Null, data2: checklabel.data.clone(), register: 0, line: ast.line});
 			
-			let label = program.labels.len();
-			program.labels.push(curr_pos.to_owned() + ret_val.len());
+			//label to goto
+			let label = new_label(label_count);
+			let label_data = label.data.clone();
+			ret_val.push(label);
 
 			//Block
-			ret_val.append(&mut linearize(&mut ast.children[8], curr_reg, curr_pos + ret_val.len(), program));
+			ret_val.append(&mut linearize(&mut ast.children[8], curr_reg, label_count, program)?);
+			
+			//Modify iterator
+			ret_val.append(&mut linearize(&mut ast.children[6], curr_reg, label_count, program)?);
 			
 			//Loop Check
-			ret_val.append(&mut linearize(&mut ast.children[4], curr_reg, curr_pos + ret_val.len(), program));
+			ret_val.push(checklabel);
+			ret_val.append(&mut linearize(&mut ast.children[4], curr_reg, label_count, program)?);
 			let reg = Data::Register(ret_val.last().unwrap().register);
 			
-			//Modify iterator
-			ret_val.append(&mut linearize(&mut ast.children[6], curr_reg, curr_pos + ret_val.len(), program));
-			
 			//goto label at end of loop if true
-			ret_val.push(Opcode{instruction: "FOR_GOTO".to_string(), data: reg, data2: Data::Label(label), register: 0, line: 0});
+			ret_val.push(Opcode{instruction: "FOR_GOTO".to_string(), data: reg, data2: label_data, register: 0, line: ast.line});
 		}
 		"If" => {
-			ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program));
+			ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, label_count, program)?);
 			
 			//goto label if false
-			let iffalselabel = program.labels.len();
-			ret_val.push(Opcode{instruction: "CHECK_IF_NOT".to_string(), data: Data::Register(ret_val.last().unwrap().register), data2: Data::Label(iffalselabel), register: 0, line: 0});
-			program.labels.push(0);
+			let iffalselabel = new_label(label_count);
+			ret_val.push(Opcode{instruction: "CHECK_IF_NOT".to_string(), data: Data::Register(ret_val.last().unwrap().register), data2: iffalselabel.data.clone(), register: 0, line: ast.line});
 			
-			ret_val.append(&mut linearize(&mut ast.children[2], curr_reg, curr_pos + ret_val.len(), program));
+			ret_val.append(&mut linearize(&mut ast.children[2], curr_reg, label_count, program)?);
 			
 			//goto label
-			let iftruelabel = program.labels.len();
-			ret_val.push(Opcode{instruction: "CHECK_ELSE_NOT".to_string(), data: Data::Null, data2: Data::Label(iftruelabel), register: 0, line: 0});
-			program.labels.push(0);
+			let iftruelabel = new_label(label_count);
+			ret_val.push(Opcode{instruction: "CHECK_ELSE_NOT".to_string(), data: Data::Null, data2: iftruelabel.data.clone(), register: 0, line: ast.line});
 			
 			//label to goto
-			program.labels[iffalselabel] = curr_pos.to_owned() + ret_val.len();
-			//ret_val.push(Opcode{instruction: "ELSE_LABEL".to_string(), data: Data::Label(else_label_reg), data2: Data::Null, register: 0, line: 0});
+			ret_val.push(iffalselabel);
 			
-			ret_val.append(&mut linearize(&mut ast.children[3], curr_reg, curr_pos + ret_val.len(), program));
+			ret_val.append(&mut linearize(&mut ast.children[3], curr_reg, label_count, program)?);
 			
 			//label to goto
-			program.labels[iftruelabel] = curr_pos.to_owned() + ret_val.len();
-			//ret_val.push(Opcode{instruction: "IF_LABEL".to_string(), data: Data::Label(if_label_reg), data2: Data::Null, register: 0, line: 0});
+			ret_val.push(iftruelabel);
+		}
+		"With" => {
+			ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, label_count, program)?);
+			
+			//skip the block if there's nothing to run it as
+			let endlabel = new_label(label_count);
+			ret_val.push(Opcode{instruction: "WITH".to_string(), data: Data::Register(ret_val.last().unwrap().register), data2: endlabel.data.clone(), register: 0, line: ast.line});
+			
+			//label to goto for the next instance
+			let label = new_label(label_count);
+			let label_data = label.data.clone();
+			ret_val.push(label);
+			
+			ret_val.append(&mut linearize(&mut ast.children[2], curr_reg, label_count, program)?);
+			
+			ret_val.push(Opcode{instruction: "WITH_NEXT".to_string(), data: Data::Null, data2: label_data, register: 0, line: ast.line});
+			ret_val.push(endlabel);
 		}
 		"Else" => {
 			if ast.children.len() > 1 {
-				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program));
+				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, label_count, program)?);
 			}
 		}
 		"Stat" => {
 			if ast.children.len() == 3 || ast.children[0].rule == "COLON" {
-				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program));
+				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, label_count, program)?);
+			} else if ast.children[0].rule == "RETURN" {
+				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, label_count, program)?);
+				ret_val.push(Opcode{instruction: "RETURN".to_string(), data: Data::Register(ret_val[ret_val.len() - 1].register), data2: Data::Null, register: 0, line: ast.line});
 			} else if ast.children[0].rule == "SET" {
-				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program));
+				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, label_count, program)?);
 				ret_val.push(Opcode{instruction: "ID".to_string(), data: Data::Variable("print".to_owned()), data2: Data::Null, register: *curr_reg, line: ast.line});
 				*curr_reg += 1;
 				ret_val.push(Opcode{instruction: "FUNC".to_string(), data: Data::Register(*curr_reg - 1), data2: Data::Register(ret_val[ret_val.len() - 2].register), register: *curr_reg, line: ast.line});
 				*curr_reg += 1;
 			} else {
-				ret_val.append(&mut linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program));
+				ret_val.append(&mut linearize(&mut ast.children[0], curr_reg, label_count, program)?);
 			}
 		}
 		"Stat2" => {
-			if ast.children.len() == 3 {
-				let mut id = linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program);
-				let mut stat = linearize(&mut ast.children[2], curr_reg, curr_pos + ret_val.len(), program);
+			if ast.children.len() == 3 && ast.children[2].children[0].rule == "ID" {
+				//owner.name is one variable (see find_scope), so global.x = 1 sets x on global instead of working out global first
+				let owner = ast.children[0].data.as_ref().unwrap().1.to_owned();
+				let inner = &mut ast.children[2];
+				let name = &mut inner.children[0].data.as_mut().unwrap().1;
+				*name = format!("{}.{}", owner, name);
+				ret_val.append(&mut linearize(inner, curr_reg, label_count, program)?);
+			} else if ast.children.len() == 3 {
+				let mut id = linearize(&mut ast.children[0], curr_reg, label_count, program)?;
+				let mut stat = linearize(&mut ast.children[2], curr_reg, label_count, program)?;
 				ret_val.append(&mut id);
 				ret_val.append(&mut stat);
 				ret_val.push(Opcode{instruction: "DOT".to_string(), data: Data::Register(id[id.len() - 1].register), data2: Data::Register(stat[stat.len() - 1].register), register: *curr_reg, line: ast.line});
 				*curr_reg += 1;
 			}else{
-				let mut child0 = linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program);
-				let mut child1 = linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program);
+				let mut child0 = linearize(&mut ast.children[0], curr_reg, label_count, program)?;
+				let mut child1 = linearize(&mut ast.children[1], curr_reg, label_count, program)?;
 				if child1.len() > 0 {
 					let index = child1.len()-1;
 					child1[index].data = Data::Register(child0[0].register);
@@ -106,7 +205,8 @@ pub fn linearize(ast: &mut ASTNode, curr_reg: &mut u32, curr_pos: usize, program
 			}
 		}
 		"Func" => {
-			ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program));
+			let mut args: Vec<&mut ASTNode> = ast.children.iter_mut().filter(|node| !["LPAREN", "RPAREN", "COMMA", "NONE"].contains(&node.rule.as_str())).collect();
+			ret_val.append(&mut linearize_comma_list(&mut args, curr_reg, label_count, program)?);
 			if ret_val.len() > 0 {
 				ret_val.push(Opcode{instruction: "FUNC".to_string(), data: Data::Null, data2: Data::Register(ret_val[ret_val.len() - 1].register), register: *curr_reg, line: ast.line});
 			}else{
@@ -114,123 +214,86 @@ pub fn linearize(ast: &mut ASTNode, curr_reg: &mut u32, curr_pos: usize, program
 			}
 			*curr_reg += 1;
 		}
-		"Comma" => {
-			if ast.children.len() == 2 {
-				let mut child0 = linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program);
-				let reg_1 = Data::Register(child0[child0.len() - 1].register);
-				let mut child1 = linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program);
-				let reg_2;
-				if child1.len() > 0 {
-					reg_2 = Data::Register(child1[child1.len() - 1].register);
-				}else{
-					reg_2 = Data::Null;
-				}
-				ret_val.append(&mut child0);
-				ret_val.append(&mut child1);
-				ret_val.push(Opcode{instruction: "Comma".to_string(), data: reg_1, data2: reg_2, register: *curr_reg, line: ast.line});
-				*curr_reg += 1;
-			} else if ast.children.len() == 3 {
-				let mut child1 = linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program);
-				let reg_1 = Data::Register(child1[child1.len() - 1].register);
-				let mut child2 = linearize(&mut ast.children[2], curr_reg, curr_pos + ret_val.len(), program);
-				let reg_2;
-				if child2.len() > 0 {
-					reg_2 = Data::Register(child2[child2.len() - 1].register);
-				}else{
-					reg_2 = Data::Null;
+		"FuncDef" => {
+			//A body without {} runs until the next definition, which the grammar leaves at the end of the body's Block,
+			//so it gets taken back out and made its own function
+			let next_definition = take_next_definition(&mut ast.children[4]);
+			let name = ast.children[1].data.as_ref().unwrap().1.to_owned();
+			let input_types = ast.children[2].children.iter().filter(|node| node.rule == "Arg").map(|arg| {
+				if arg.children.len() == 3 {
+					return Data::Type(arg.children[0].data.as_ref().unwrap().1.to_owned());
 				}
-				ret_val.append(&mut child1);
-				ret_val.append(&mut child2);
-				ret_val.push(Opcode{instruction: "Comma".to_string(), data: reg_1, data2: reg_2, register: *curr_reg, line: ast.line});
-				*curr_reg += 1;
+				return Data::Type("var".to_string());
+			}).collect();
+			//Function bodies get their own label table, so their labels start from 0 again
+			let mut function_labels = 0;
+			let mut ops = linearize(&mut ast.children[2], curr_reg, &mut function_labels, program)?;
+			ops.append(&mut linearize(&mut ast.children[4], curr_reg, &mut function_labels, program)?);
+			program.functions.insert(name, Rc::new(resolve_labels(FuncData{return_type: Data::Null, input_types, optional_types: HashMap::new()}, ops)));
+			if let Some(mut next_definition) = next_definition {
+				ret_val.append(&mut linearize(&mut next_definition, curr_reg, label_count, program)?);
 			}
 		}
-		"FuncDef" => {
-			//ret_val.push(Opcode{instruction: "FUNC_DEF".to_string(), data: Data::Variable(ast.children[1].data.as_ref().unwrap().1.to_owned()), data2: Data::Type("var".to_string()), register: *curr_reg, line: 0});
-			//*curr_reg += 1;
-			ret_val.append(&mut linearize(&mut ast.children[2], curr_reg, curr_pos + ret_val.len(), program));
-			ret_val.append(&mut linearize(&mut ast.children[3], curr_reg, curr_pos + ret_val.len(), program));
-			let ops = linearize(&mut ast.children[4], curr_reg, 1, program);
-			program.functions.insert(ast.children[1].data.as_ref().unwrap().1.to_owned(), (FuncData{return_type: Data::Null, input_types: vec![], optional_types: HashMap::new()}, ops));
-			//ret_val.push(Opcode{instruction: "END_FUNC".to_string(), data: Data::Variable(ast.children[1].data.as_ref().unwrap().1.to_owned()), data2: Data::Null, register: *curr_reg, line: 0});
-			//*curr_reg += 1;
-		}
 		"FuncDefArgs" => {
-			if ast.children.len() == 3 {
-				let mut child = linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program);
-				ret_val.push(Opcode{instruction: "FUNC_ARGS".to_string(), data: Data::Int(child.len() as i32), data2: Data::Null, register: *curr_reg, line: 0});
-				*curr_reg += 1;
-				ret_val.append(&mut child);
-				//ret_val.append(&mut linearize(&mut ast.children[3], curr_reg, curr_pos + ret_val.len(), program));
-			} else {
-				ret_val.push(Opcode{instruction: "FUNC_ARGS".to_string(), data: Data::Null, data2: Data::Null, register: *curr_reg, line: 0});
-				*curr_reg += 1;
-				ret_val.append(&mut linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program));
+			//the parameters go at the start of the function, and take its arguments in order
+			for arg in ast.children.iter_mut().filter(|node| node.rule == "Arg") {
+				ret_val.append(&mut linearize(arg, curr_reg, label_count, program)?);
 			}
 		}
+		//imports are linked in by parser_setup once the program is compiled (see modules)
+		"Import" | "From" => {}
 		"FuncDefType" => {
 			
-		}
-		"DefComma" => {
-			if ast.children.len() > 1 {
-				let mut child0 = linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program);
-				let reg_0 = Data::Register(child0[child0.len() - 1].register);
-				let mut child1 = linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program);
-				let reg_1;
-				if child1.len() > 0 {
-					reg_1 = Data::Register(child1[child1.len() - 1].register);
-				}else{
-					reg_1 = Data::Null;
-				}
-				ret_val.append(&mut child0);
-				ret_val.append(&mut child1);
-				ret_val.push(Opcode{instruction: "Comma".to_string(), data: reg_0, data2: reg_1, register: *curr_reg, line: 0});
-				*curr_reg += 1;
-			}
 		}
 		"Arg" => {
-			if ast.children.len() == 3 {
-				ret_val.push(Opcode{instruction: "ARG".to_string(), data: Data::Variable(ast.children[1].data.as_ref().unwrap().1.to_owned()), data2: Data::Type(ast.children[0].data.as_ref().unwrap().1.to_owned()), register: 0, line: 0});
-				ret_val.append(&mut linearize(&mut ast.children[2], curr_reg, curr_pos + ret_val.len(), program));
-			}else{
-				ret_val.push(Opcode{instruction: "ARG".to_string(), data: Data::Variable(ast.children[0].data.as_ref().unwrap().1.to_owned()), data2: Data::Type("var".to_string()), register: 0, line: 0});
-				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program));
-			}
+			//TYPE ID ArgDefault or ID ArgDefault
+			let (typ, index) = if ast.children.len() == 3 { (ast.children[0].data.as_ref().unwrap().1.to_owned(), 1) } else { ("var".to_string(), 0) };
+			let name = ast.children[index].data.as_ref().unwrap().1.to_owned();
+			ret_val.push(Opcode{instruction: "Declare".to_string(), data: Data::Type(typ), data2: Data::Variable(name.to_owned()), register: 0, line: ast.line});
+			let mut default = linearize(&mut ast.children[index + 1], curr_reg, label_count, program)?;
+			let default_value = match default.last() {
+				Some(op) => Data::Register(op.register),
+				None => Data::Null,
+			};
+			ret_val.append(&mut default);
+			ret_val.push(Opcode{instruction: "ARG".to_string(), data: Data::Variable(name), data2: default_value, register: 0, line: ast.line});
 		}
 		"ArgDefault" => {
 			if ast.children.len() == 2 {
-				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program));
+				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, label_count, program)?);
 			}
 		}
 		"ID" => {
-			ret_val.push(Opcode{instruction: "ID".to_string(), data: Data::Variable(ast.data.as_ref().unwrap().1.to_owned()), data2: Data::Null, register: *curr_reg, line: 0});
+			ret_val.push(Opcode{instruction: "ID".to_string(), data: Data::Variable(ast.data.as_ref().unwrap().1.to_owned()), data2: Data::Null, register: *curr_reg, line: ast.line});
 			*curr_reg += 1;
 		}
 		"DECIMAL" => {
-			if !Decimal::from_str(ast.data.as_ref().unwrap().1.as_str()).is_err() {
-				ret_val.push(Opcode{instruction: "Value".to_string(), data: Data::Decimal(Decimal::from_str(ast.data.as_ref().unwrap().1.as_str()).unwrap()), data2: Data::Null, register: *curr_reg, line: 0});
-				*curr_reg += 1;
-			}
+			ret_val.push(Opcode{instruction: "Value".to_string(), data: Data::Decimal(number_literal(ast)?), data2: Data::Null, register: *curr_reg, line: ast.line});
+			*curr_reg += 1;
 		}
 		"INT" => {
 			//OVERRIDES TO USE DECIMAL BY DEFAULT
 			//ret_val.push(Opcode{instruction: "Value".to_string(), data: Data::Int(ast.data.as_ref().unwrap().1.parse::<i32>().unwrap()), data2: Data::Null, register: *curr_reg, line: 0});
-			ret_val.push(Opcode{instruction: "Value".to_string(), data: Data::Decimal(Decimal::from_str(ast.data.as_ref().unwrap().1.as_str()).unwrap()), data2: Data::Null, register: *curr_reg, line: 0});
+			ret_val.push(Opcode{instruction: "Value".to_string(), data: Data::Decimal(number_literal(ast)?), data2: Data::Null, register: *curr_reg, line: ast.line});
 			*curr_reg += 1;
 		}
 		"STRING" => {
-			ret_val.push(Opcode{instruction: "Value".to_string(), data: Data::String(ast.data.as_ref().unwrap().1.to_owned()[1..ast.data.as_ref().unwrap().1.len() - 1].to_string()), data2: Data::Null, register: *curr_reg, line: 0});
+			ret_val.push(Opcode{instruction: "Value".to_string(), data: Data::String(ast.data.as_ref().unwrap().1.to_owned()[1..ast.data.as_ref().unwrap().1.len() - 1].to_string()), data2: Data::Null, register: *curr_reg, line: ast.line});
+			*curr_reg += 1;
+		}
+		"COLOR" => {
+			ret_val.push(Opcode{instruction: "Value".to_string(), data: hex_color(&ast.data.as_ref().unwrap().1), data2: Data::Null, register: *curr_reg, line: ast.line});
 			*curr_reg += 1;
 		}
 		"TYPE" => {
-			ret_val.push(Opcode{instruction: "Value".to_string(), data: Data::Type(ast.data.as_ref().unwrap().1.to_owned()[1..ast.data.as_ref().unwrap().1.len() - 1].to_string()), data2: Data::Null, register: *curr_reg, line: 0});
+			ret_val.push(Opcode{instruction: "Value".to_string(), data: Data::Type(ast.data.as_ref().unwrap().1.to_owned()[1..ast.data.as_ref().unwrap().1.len() - 1].to_string()), data2: Data::Null, register: *curr_reg, line: ast.line});
 			*curr_reg += 1;
 		}
 		"OpPrec1" | "OpPrec2" | "OpPrec3" | "OpPrec4" => {
-			let mut child0 = linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program);
+			let mut child0 = linearize(&mut ast.children[0], curr_reg, label_count, program)?;
 			let reg = Data::Register(child0.last().unwrap().register);
 			ret_val.append(&mut child0);
-			let mut op_list = linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program);
+			let mut op_list = linearize(&mut ast.children[1], curr_reg, label_count, program)?;
 			if op_list.len() > 1 {
 				let index = op_list.iter().position(|r| r.instruction == "FLAG");
 				if index.is_some() {
@@ -242,12 +305,12 @@ pub fn linearize(ast: &mut ASTNode, curr_reg: &mut u32, curr_pos: usize, program
 		}
 		"OpExp" => {
 			if ast.children.len() > 2 {
-				let child0 = linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program);
-				let mut child1 = linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program);
+				let child0 = linearize(&mut ast.children[0], curr_reg, label_count, program)?;
+				let mut child1 = linearize(&mut ast.children[1], curr_reg, label_count, program)?;
 				let mut reg = Data::Register(child1[child1.len() - 1].register);
 				ret_val.append(&mut child1);
 				let index = ret_val.len();
-				let mut child2 = linearize(&mut ast.children[2], curr_reg, curr_pos + ret_val.len(), program);
+				let mut child2 = linearize(&mut ast.children[2], curr_reg, label_count, program)?;
 				if child2.iter().position(|r| r.instruction == "FLAG").is_some() {
 					//set first op's left side to register
 					let _index = child2.iter().position(|r| r.instruction == "FLAG").unwrap();
@@ -258,29 +321,29 @@ pub fn linearize(ast: &mut ASTNode, curr_reg: &mut u32, curr_pos: usize, program
 					ret_val.append(&mut child2);
 				}
 				//pushing a custom instruction here as an indicator for OpPriority to handle
-				ret_val.push(Opcode{instruction: "FLAG".to_string(), data: Data::Null, data2: Data::Null, register: 0, line: 0});
+				ret_val.push(Opcode{instruction: "FLAG".to_string(), data: Data::Null, data2: Data::Null, register: 0, line: ast.line});
 				//create new operator, keep track of register
 				ret_val.push(Opcode{instruction: child0[0].instruction.to_owned(), data: Data::Null, data2: reg, register: *curr_reg, line: ast.line});
 				*curr_reg += 1;
 			}else if ast.children.len() == 1 {
-				let mut child = linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program);
+				let mut child = linearize(&mut ast.children[0], curr_reg, label_count, program)?;
 				ret_val.append(&mut child);
 			}
 		}
 		"OpMD" | "OpAS" | "OpCmp" => {
 			if ast.children.len() > 2 {
-				let child0 = linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program);
-				let mut child1 = linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program);
+				let child0 = linearize(&mut ast.children[0], curr_reg, label_count, program)?;
+				let mut child1 = linearize(&mut ast.children[1], curr_reg, label_count, program)?;
 				let mut reg = Data::Register(child1[child1.len() - 1].register);
 				ret_val.append(&mut child1);
 				//pushing a custom instruction here as an indicator for OpPriority to handle
 				let index = ret_val.len();
-				ret_val.push(Opcode{instruction: "FLAG".to_string(), data: Data::Null, data2: Data::Null, register: 0, line: 0});
+				ret_val.push(Opcode{instruction: "FLAG".to_string(), data: Data::Null, data2: Data::Null, register: 0, line: ast.line});
 				//create new operator, keep track of register
 				ret_val.push(Opcode{instruction: child0[0].instruction.to_owned(), data: Data::Null, data2: reg, register: *curr_reg, line: ast.line});
 				reg = Data::Register(*curr_reg);
 				*curr_reg += 1;
-				let mut child2 = linearize(&mut ast.children[2], curr_reg, curr_pos + ret_val.len(), program);
+				let mut child2 = linearize(&mut ast.children[2], curr_reg, label_count, program)?;
 				if child2.iter().position(|r| r.instruction == "FLAG").is_some() {
 					//set first op's left side to register
 					let _index = child2.iter().position(|r| r.instruction == "FLAG").unwrap();
@@ -290,38 +353,58 @@ pub fn linearize(ast: &mut ASTNode, curr_reg: &mut u32, curr_pos: usize, program
 					ret_val.append(&mut child2);
 				}
 			}else if ast.children.len() == 1 {
-				let mut child = linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program);
+				let mut child = linearize(&mut ast.children[0], curr_reg, label_count, program)?;
 				ret_val.append(&mut child);
 			}
 		}
 		"Unit" => {
 			if ast.children.len() == 4 {
-				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program));
+				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, label_count, program)?);
 				//maybe do type hint stuff
+			}else if ast.children[0].rule == "MINUS" {
+				//-x is worked out as 0 - x
+				let mut child = linearize(&mut ast.children[1], curr_reg, label_count, program)?;
+				let reg = Data::Register(child[child.len() - 1].register);
+				ret_val.append(&mut child);
+				ret_val.push(Opcode{instruction: "MINUS".to_string(), data: Data::Decimal(Decimal::from(0)), data2: reg, register: *curr_reg, line: ast.line});
+				*curr_reg += 1;
 			}else{
-				ret_val.append(&mut linearize(&mut ast.children[0], curr_reg, curr_pos + ret_val.len(), program));
+				ret_val.append(&mut linearize(&mut ast.children[0], curr_reg, label_count, program)?);
 				//maybe do type hint stuff
 			}
 		}
 		"Def" => {
-			ret_val.push(Opcode{instruction: "Declare".to_string(), data: Data::Type(ast.children[0].data.as_ref().unwrap().1.to_owned()), data2: Data::Variable(ast.children[1].data.as_ref().unwrap().1.to_owned()), register: 0, line: ast.line});
-			if ast.children.len() == 4 {
-				let mut child = linearize(&mut ast.children[3], curr_reg, curr_pos + ret_val.len(), program);
-				let reg = Data::Register(child[child.len() - 1].register);
-				ret_val.append(&mut child);
-				ret_val.push(Opcode{instruction: "Set".to_string(), data: Data::Variable(ast.children[1].data.as_ref().unwrap().1.to_owned()), data2: reg, register: *curr_reg, line: ast.line});
-				*curr_reg += 1;
+			//TYPE ID, with an optional Set Expr after it, and more COMMA ID (Set Expr) after that in dialects that allow var a = 1, b
+			let typ = ast.children[0].data.as_ref().unwrap().1.to_owned();
+			let mut index = 1;
+			while index < ast.children.len() {
+				if ast.children[index].rule != "ID" {
+					index += 1;
+					continue;
+				}
+				let name = ast.children[index].data.as_ref().unwrap().1.to_owned();
+				ret_val.push(Opcode{instruction: "Declare".to_string(), data: Data::Type(typ.to_owned()), data2: Data::Variable(name.to_owned()), register: 0, line: ast.line});
+				if index + 2 < ast.children.len() && ast.children[index + 1].rule == "Set" {
+					let mut child = linearize(&mut ast.children[index + 2], curr_reg, label_count, program)?;
+					let reg = Data::Register(child[child.len() - 1].register);
+					ret_val.append(&mut child);
+					ret_val.push(Opcode{instruction: "Set".to_string(), data: Data::Variable(name), data2: reg, register: *curr_reg, line: ast.line});
+					*curr_reg += 1;
+					index += 3;
+				} else {
+					index += 1;
+				}
 			}
 		}
 		"AsgnOp" => {
 			if ast.children.len() == 2 {
-				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, curr_pos + ret_val.len(), program));
+				ret_val.append(&mut linearize(&mut ast.children[1], curr_reg, label_count, program)?);
 				let reg = Data::Register(ret_val[ret_val.len() - 1].register);
 				ret_val.push(Opcode{instruction: "Set".to_string(), data: Data::Null, data2: reg, register: *curr_reg, line: ast.line});
 				*curr_reg += 1;
 			} else if ast.children.len() > 0 {
 				for i in &mut ast.children {
-					ret_val.append(&mut linearize(i, curr_reg, curr_pos + ret_val.len(), program));
+					ret_val.append(&mut linearize(i, curr_reg, label_count, program)?);
 				}
 			}
 		}
@@ -332,10 +415,10 @@ pub fn linearize(ast: &mut ASTNode, curr_reg: &mut u32, curr_pos: usize, program
 			}else if ast.children.len() > 0 {
 				//ret_val.push(Opcode{instruction: ast.rule.to_owned(), data: "UNIMPLEMENTED".to_string(), data2: "".to_string(), register: 0, line: ast.line});
 				for i in &mut ast.children {
-					ret_val.append(&mut linearize(i, curr_reg, curr_pos + ret_val.len(), program));
+					ret_val.append(&mut linearize(i, curr_reg, label_count, program)?);
 				}
 			}
 		}
 	}
-	return ret_val;
+	return Ok(ret_val);
 }
\ No newline at end of file
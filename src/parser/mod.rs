@@ -1,3 +1,4 @@
 pub mod linearize_ast;
 pub mod parser_setup;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod modules;
\ No newline at end of file
@@ -0,0 +1,274 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::datatypes::*;
+use crate::parser::parser_setup::{compile_source, dialect_for_file};
+
+//One import statement, found in the AST before it gets linearized
+pub struct Import {
+	pub path: String,
+	pub kind: ImportKind,
+	pub line: i32,
+}
+
+pub enum ImportKind {
+	//import "lib/enemies.gel" gets everything as enemies.name, or as alias.name with import "..." as alias
+	Namespace(String),
+	//from "util.gel" import clamp, wrap gets just those, by their own names
+	Names(Vec<String>),
+}
+
+//A module that's been compiled. Its functions are all named namespace.name, including the ones it uses from its own imports,
+//so they still find each other once they're copied into another program.
+struct Module {
+	namespace: String,
+	functions: HashMap<String, Rc<Function>>,
+	//the names of the functions the module defines itself, which are the ones that can be imported
+	exports: Vec<String>,
+}
+
+//Everything needed to compile imports for one program. Each module is only compiled once, however many files import it.
+//Imports are looked for next to the file doing the importing, then in every folder in GEL_PATH, then in the working directory.
+//Only a module's functions are imported, the code at the top of it doesn't run.
+pub struct Modules {
+	search_path: Vec<PathBuf>,
+	compiled: HashMap<PathBuf, Module>,
+	//the modules being compiled right now, in the order they imported each other, to catch import cycles
+	compiling: Vec<PathBuf>,
+}
+
+impl Modules {
+	pub fn new() -> Modules {
+		let mut search_path = vec![];
+		if let Some(paths) = env::var_os("GEL_PATH") {
+			search_path.extend(env::split_paths(&paths));
+		}
+		search_path.push(PathBuf::from("."));
+		return Modules{search_path, compiled: HashMap::new(), compiling: vec![]};
+	}
+
+	//Every module that got compiled, for watching them
+	pub fn files(&self) -> Vec<PathBuf> {
+		return self.compiled.keys().cloned().collect();
+	}
+
+	//Marks the file being compiled, so a module importing it back is caught as a cycle
+	pub fn start(&mut self, filename: &str) {
+		if let Ok(path) = fs::canonicalize(filename) {
+			self.compiling.push(path);
+		}
+	}
+
+	//Copies what an import asks for into program, compiling the module first if it hasn't been yet
+	pub fn link(&mut self, program: &mut Program, import: &Import, dir: &Path) -> Result<(), String> {
+		let path = self.find(&import.path, dir).ok_or_else(|| format!("Couldn't find {} to import on line {}, looked next to the file, in GEL_PATH and in the working directory", import.path, import.line))?;
+		if let Some(position) = self.compiling.iter().position(|compiling| *compiling == path) {
+			let cycle: Vec<String> = self.compiling[position..].iter().chain([&path]).map(|path| path.display().to_string()).collect();
+			return Err(format!("Import cycle on line {}: {}", import.line, cycle.join(" imports ")));
+		}
+		if !self.compiled.contains_key(&path) {
+			let module = self.compile(&path)?;
+			self.compiled.insert(path.to_owned(), module);
+		}
+		let module = &self.compiled[&path];
+
+		for (name, function) in &module.functions {
+			program.functions.insert(name.to_owned(), Rc::clone(function));
+		}
+		match &import.kind {
+			ImportKind::Namespace(namespace) => {
+				for export in &module.exports {
+					let name = format!("{}.{}", namespace, export);
+					let function = &module.functions[&format!("{}.{}", module.namespace, export)];
+					if let Some(existing) = program.functions.get(&name) {
+						if !Rc::ptr_eq(existing, function) {
+							return Err(format!("{} on line {} is already imported from somewhere else, use import \"{}\" as another_name", name, import.line, import.path));
+						}
+					}
+					program.functions.insert(name, Rc::clone(function));
+				}
+			}
+			ImportKind::Names(names) => {
+				for name in names {
+					if !module.exports.contains(name) {
+						return Err(format!("{} doesn't have a function called {} to import on line {}", import.path, name, import.line));
+					}
+					let function = &module.functions[&format!("{}.{}", module.namespace, name)];
+					if let Some(existing) = program.functions.get(name) {
+						if !Rc::ptr_eq(existing, function) {
+							return Err(format!("{} on line {} is already defined or imported from somewhere else, use import \"{}\" to keep it in its namespace", name, import.line, import.path));
+						}
+					}
+					program.functions.insert(name.to_owned(), Rc::clone(function));
+				}
+			}
+		}
+		return Ok(());
+	}
+
+	fn find(&self, path: &str, dir: &Path) -> Option<PathBuf> {
+		let candidates = [dir.to_path_buf()].into_iter().chain(self.search_path.iter().cloned());
+		for folder in candidates {
+			if let Ok(found) = fs::canonicalize(folder.join(path)) {
+				if found.is_file() {
+					return Some(found);
+				}
+			}
+		}
+		return None;
+	}
+
+	fn compile(&mut self, path: &Path) -> Result<Module, String> {
+		let input = fs::read_to_string(path).map_err(|error| format!("Something went wrong reading {}: {}", path.display(), error))?;
+		let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+		self.compiling.push(path.to_path_buf());
+		let compiled = compile_source(input, dialect_for_file(&path.to_string_lossy()), &dir, self);
+		self.compiling.pop();
+		let (program, exports) = compiled.map_err(|error| format!("{} has a problem!\n{}", path.display(), error))?;
+
+		//two different lib/util.gel and util.gel can't both be util
+		let stem = path.file_stem().map_or("module".to_string(), |stem| stem.to_string_lossy().to_string());
+		let mut namespace = stem.to_owned();
+		let mut count = 1;
+		while self.compiled.values().any(|module| module.namespace == namespace) {
+			count += 1;
+			namespace = format!("{}{}", stem, count);
+		}
+
+		//Everything the module calls by a name of its own gets pointed at namespace.name instead,
+		//and the functions it imported by name get pointed at the module they came from
+		let mut renames = HashMap::new();
+		for (name, function) in &program.functions {
+			if name == "" {
+				continue;
+			}
+			match self.full_name(function) {
+				Some(full_name) => {
+					renames.insert(name.to_owned(), full_name);
+				}
+				None => {
+					renames.insert(name.to_owned(), format!("{}.{}", namespace, name));
+				}
+			}
+		}
+		let mut functions = HashMap::new();
+		for (name, function) in &program.functions {
+			if name == "" {
+				continue;
+			}
+			if renames[name].starts_with(&format!("{}.", namespace)) {
+				functions.insert(renames[name].to_owned(), Rc::new(rename_calls(function, &renames)));
+			} else {
+				functions.insert(renames[name].to_owned(), Rc::clone(function));
+			}
+		}
+		return Ok(Module{namespace, functions, exports});
+	}
+
+	//The namespace.name of a function that came out of a module that's already been compiled
+	fn full_name(&self, function: &Rc<Function>) -> Option<String> {
+		for module in self.compiled.values() {
+			for (name, compiled) in &module.functions {
+				if Rc::ptr_eq(compiled, function) {
+					return Some(name.to_owned());
+				}
+			}
+		}
+		return None;
+	}
+}
+
+//Finds the import statements anywhere in a program
+pub fn find_imports(ast: &ASTNode) -> Vec<Import> {
+	let mut ret_val = vec![];
+	match ast.rule.as_str() {
+		"Import" => {
+			let path = string_literal(&ast.children[1]);
+			let namespace = match ast.children[2].children.get(1) {
+				Some(alias) => alias.data.as_ref().unwrap().1.to_owned(),
+				None => Path::new(&path).file_stem().map_or(path.to_owned(), |stem| stem.to_string_lossy().to_string()),
+			};
+			ret_val.push(Import{path, kind: ImportKind::Namespace(namespace), line: ast.line});
+		}
+		"From" => {
+			let names = ast.children.iter().filter(|node| node.rule == "ID").map(|node| node.data.as_ref().unwrap().1.to_owned()).collect();
+			ret_val.push(Import{path: string_literal(&ast.children[1]), kind: ImportKind::Names(names), line: ast.line});
+		}
+		_ => {
+			for child in &ast.children {
+				ret_val.append(&mut find_imports(child));
+			}
+		}
+	}
+	return ret_val;
+}
+
+fn string_literal(node: &ASTNode) -> String {
+	let text = &node.data.as_ref().unwrap().1;
+	return text[1..text.len() - 1].to_string();
+}
+
+//A copy of function with the functions it uses renamed: the ones it calls, and any other name it reads that isn't one of its own variables.
+//Names it declares or assigns to are left alone, since those are always variables.
+fn rename_calls(function: &Function, renames: &HashMap<String, String>) -> Function {
+	let mut opcodes = function.opcodes.to_vec();
+	let mut locals = HashSet::new();
+	//registers of the ID ops that load what a FUNC calls, and of the ones a Set or INCR assigns to
+	let mut callees = HashSet::new();
+	let mut assigned = HashSet::new();
+	for op in &opcodes {
+		match (op.instruction.as_str(), &op.data, &op.data2) {
+			("Declare", _, Data::Variable(name)) | ("ARG", Data::Variable(name), _) => {
+				locals.insert(name.to_owned());
+			}
+			("FUNC", Data::Register(reg), _) => {
+				callees.insert(*reg);
+			}
+			("Set", Data::Register(reg), _) | ("INCR", Data::Register(reg), _) => {
+				assigned.insert(*reg);
+			}
+			_ => {}
+		}
+	}
+	let no_locals = HashSet::new();
+	for op in &mut opcodes {
+		match op.instruction.as_str() {
+			"Declare" | "Set" | "ARG" | "INCR" => {}
+			"ID" if assigned.contains(&op.register) => {}
+			"ID" if callees.contains(&op.register) => {
+				rename_data(&mut op.data, renames, &no_locals);
+			}
+			"FUNC" => {
+				//the optimizer can put the function's name right in the FUNC instead of loading it first
+				rename_data(&mut op.data, renames, &no_locals);
+				rename_data(&mut op.data2, renames, &locals);
+			}
+			_ => {
+				rename_data(&mut op.data, renames, &locals);
+				rename_data(&mut op.data2, renames, &locals);
+			}
+		}
+	}
+	let data = FuncData{return_type: function.data.return_type.clone(), input_types: function.data.input_types.to_vec(), optional_types: function.data.optional_types.clone()};
+	return Function{data, opcodes, labels: function.labels.to_vec()};
+}
+
+fn rename_data(data: &mut Data, renames: &HashMap<String, String>, locals: &HashSet<String>) {
+	match data {
+		Data::Variable(name) => {
+			if locals.contains(name) {
+				return;
+			}
+			if let Some(renamed) = renames.get(name) {
+				*name = renamed.to_owned();
+			}
+		}
+		Data::Comma(left, right) => {
+			rename_data(left, renames, locals);
+			rename_data(right, renames, locals);
+		}
+		_ => {}
+	}
+}
@@ -1,25 +1,22 @@
-use std::collections::HashMap;
-use once_cell::sync::Lazy;
-use crate::datatypes::GrammarToken;
+use crate::parse_table::GrammarToken;
 use crate::datatypes::ASTNode;
+use crate::parse_table::ParseTable;
 
 //A Top-Down Parser
-pub fn parser(token_list: Vec<(String, String, i32)>, grammar: &Lazy<HashMap<String, Vec<Vec<GrammarToken>>>>) -> Result<ASTNode, String> {
-	let none = GrammarToken{is_terminal: true, value: String::from("NONE"), lookahead: vec!["NONE".to_string()], is_subrule: false};
-	let end = GrammarToken{is_terminal: true, value: String::from("END"), lookahead: vec!["".to_string()], is_subrule: false};
-	let endtoken = (String::from("END"), String::from(""), 0);
+pub fn parser(token_list: impl IntoIterator<Item = (String, String, i32)>, grammar: &ParseTable) -> Result<ASTNode, String> {
+	let none = GrammarToken{is_terminal: true, value: String::from("NONE"), is_subrule: false};
+	let end = GrammarToken{is_terminal: true, value: String::from("END"), is_subrule: false};
 	let mut ast = ASTNode{rule: "Root".to_string(), data: None, children: vec![], line: 0};
 	let mut ast_focus = vec![];
 	let mut ast_stack = vec![];
-	let mut focus = &GrammarToken{is_terminal: false, value: String::from("Root"), lookahead: vec!["END".to_string()], is_subrule: false};
+	let mut focus = &GrammarToken{is_terminal: false, value: String::from("Root"), is_subrule: false};
 	let mut stack = vec![&end];
-	let mut tokens = token_list.iter();
+	let mut tokens = token_list.into_iter();
 	//Check for an empty program
-	let first_match = tokens.next();
-	if first_match.is_none() {
+	let mut to_match = next_token(&mut tokens)?;
+	if to_match.0 == end.value {
 		return Ok(ast);
 	}
-	let mut to_match = first_match.unwrap();
 	
 	loop {
 		if to_match.0 == end.value && focus.value == none.value {
@@ -37,44 +34,19 @@ pub fn parser(token_list: Vec<(String, String, i32)>, grammar: &Lazy<HashMap<Str
 			ast_focus = ast_stack.pop().unwrap();
 			focus = stack.pop().unwrap();
 		} else if focus.is_terminal == false {
-			//The rule that will be followed next, the default value should never be used
-			let mut rule_out = grammar[&focus.value][0].iter();
-			//Make sure there is a rule to follow - if we don't have one this should fail fast
-			let mut rule_out_changed = false;
-			for rule in &grammar[&focus.value] {
-				if rule[0].value == to_match.0 {
-					rule_out = rule.iter();
-					rule_out_changed = true;
-					break;
-				}else{
-					let mut breakout = false;
-					for l in &rule[0].lookahead {
-						if to_match.0.eq(l) {
-							rule_out = rule.iter();
-							rule_out_changed = true;
-							breakout = true;
-							break;
-						}else if none.value.eq(l) && to_match.2 != -1 {
-							rule_out = rule.iter();
-							rule_out_changed = true;
-						}
+			//The rule that will be followed next, straight from the parse table
+			let rule_out = match grammar.predict(&focus.value, &to_match.0) {
+				Some(rule) => rule.iter(),
+				None => {
+					//INDENT and DEDENT can be skipped over when nothing is looking for them
+					if to_match.2 == -1 {
+						to_match = next_token(&mut tokens)?;
+						continue;
 					}
-					if breakout { break; }
+					return Err(format!("Whoops, parser error!\nI ran into a dead end thinking that I found a {:#?}, but I found a {:#?}, which was {:#?} on line {:#?}", focus.value, to_match.0, to_match.1, to_match.2));
+					//panic!("Whoops, parser error!\nI ran into a dead end thinking that I found a {:#?}, but I found a {:#?}, which was {:#?} on line {:#?}", focus.value, to_match.0, to_match.1, to_match.2);
 				}
-			}
-			if !rule_out_changed {
-				if to_match.2 == -1 {
-					let tok = tokens.next();
-					if tok != None {
-						to_match = tok.unwrap();
-					} else {
-						to_match = &endtoken;
-					}
-					continue;
-				}
-				return Err(format!("Whoops, parser error!\nI ran into a dead end thinking that I found a {:#?}, but I found a {:#?}, which was {:#?} on line {:#?}", focus.value, to_match.0, to_match.1, to_match.2));
-				//panic!("Whoops, parser error!\nI ran into a dead end thinking that I found a {:#?}, but I found a {:#?}, which was {:#?} on line {:#?}", focus.value, to_match.0, to_match.1, to_match.2);
-			}
+			};
 			for r in rule_out.rev() {
 				stack.push(r);
 				if !r.is_subrule {
@@ -98,12 +70,7 @@ pub fn parser(token_list: Vec<(String, String, i32)>, grammar: &Lazy<HashMap<Str
 			}
 			find_ast_node(&ast_focus, &mut ast).data = Some(to_match.to_owned());
 			find_ast_node(&ast_focus, &mut ast).line = to_match.2;
-			let tok = tokens.next();
-			if tok != None {
-				to_match = tok.unwrap();
-			} else {
-				to_match = &endtoken;
-			}
+			to_match = next_token(&mut tokens)?;
 			focus = stack.pop().unwrap();
 			if ast_stack.len() == 0 {
 				return Err(format!("Whoops, parser error!\nRan out of stuff in my AST to find when I found a {:#?}, which was {:#?} on line {:#?}", to_match.0, to_match.1, to_match.2));
@@ -111,12 +78,7 @@ pub fn parser(token_list: Vec<(String, String, i32)>, grammar: &Lazy<HashMap<Str
 			}
 			ast_focus = ast_stack.pop().unwrap();
 		} else if to_match.2 == -1 {
-			let tok = tokens.next();
-			if tok != None {
-				to_match = tok.unwrap();
-			} else {
-				to_match = &endtoken;
-			}
+			to_match = next_token(&mut tokens)?;
 		} else {
 			return Err(format!("Whoops, parser error!\nI was thinking I would find a {:#?}, but I found a {:#?}, which was {:#?} on line {:#?}", focus.value, to_match.0, to_match.1, to_match.2));
 			//panic!("Whoops, parser error!\nI was thinking I would find a {:#?}, but I found a {:#?}, which was {:#?} on line {:#?}", focus.value, to_match.0, to_match.1, to_match.2);
@@ -125,10 +87,45 @@ pub fn parser(token_list: Vec<(String, String, i32)>, grammar: &Lazy<HashMap<Str
 
 	//Remove subrules to clean up the AST
 	clean_ast(&mut ast);
+	check_function_bodies(&ast)?;
 
 	return Ok(ast);
 }
 
+//The next token, or END once they run out. The scanner hands over an ERROR when it can't go on (like a bad indent).
+fn next_token(tokens: &mut impl Iterator<Item = (String, String, i32)>) -> Result<(String, String, i32), String> {
+	match tokens.next() {
+		Some(token) if token.0 == "ERROR" => {
+			return Err(token.1);
+		}
+		Some(token) => {
+			return Ok(token);
+		}
+		None => {
+			return Ok((String::from("END"), String::from(""), 0));
+		}
+	}
+}
+
+//A function's body is either in {} (or under a :), or everything up to the next definition when it isn't.
+//Code after a {} body but before the next definition would quietly end up in the function, so it's an error instead.
+fn check_function_bodies(ast: &ASTNode) -> Result<(), String> {
+	if ast.rule == "FuncDef" && ast.children.len() == 5 {
+		let body = &ast.children[4];
+		if body.children.len() == 2 && body.children[0].rule == "Stat" && ["LBRACE", "COLON"].contains(&body.children[0].children[0].rule.as_str()) {
+			if let Some(after) = body.children[1].children.first().filter(|node| node.rule == "Stat") {
+				let name = &ast.children[1].data.as_ref().unwrap().1;
+				let (opener, symbol) = if body.children[0].children[0].rule == "LBRACE" { ("in {}", "{}") } else { ("under a :", ":") };
+				return Err(format!("Whoops, parser error!\nThe body of {} is {}, but there's more code after it on line {} before the next function definition.\nPut that code inside the body, or leave off the {} so the body goes until the next definition.", name, opener, after.line, symbol));
+			}
+		}
+	}
+	for child in &ast.children {
+		check_function_bodies(child)?;
+	}
+	return Ok(());
+}
+
 fn clean_ast(ast: &mut ASTNode) {
 	let mut indices = vec![];
 	for (index, node) in &mut ast.children.iter_mut().enumerate() {
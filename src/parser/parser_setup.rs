@@ -1,142 +1,192 @@
-use std::collections::HashMap;
 use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
 use once_cell::sync::Lazy;
-use crate::datatypes::GrammarToken;
+use crate::datatypes::ASTNode;
 use crate::datatypes::Program;
-use crate::datatypes::TokenAction;
+use crate::datatypes::Source;
+use crate::parse_table::TokenAction;
 use crate::parser::linearize_ast::linearize_ast;
 use crate::parser::parser::parser;
-use crate::scanner::scanner::scanner;
+use crate::parser::modules::{Modules, find_imports};
+use crate::scanner::scanner::Lexer;
+use crate::parse_table::ParseTable;
 use crate::grammar_generator::grammar_generator;
+use crate::grammar_generator::token_list_generator;
 use crate::optimizers::ast_optimizer::optimize_ast;
+use crate::optimizers::opcode_optimizer::optimize_opcodes;
+use crate::bytecode::gelc::load_program;
 
 
-//Exponents are not right-associative
-//Add foreach as an option for for loops
-//Make function definitions work without {} (if there isn't an lbrace, break on next function definition)
-static GEL_GRAMMAR: Lazy<HashMap<String, Vec<Vec<GrammarToken>>>> = Lazy::new(|| grammar_generator(String::from("
-	Root::= Block
-	Block::= Stat Block | FuncDef | NONE
-	Stat::= LBRACE Block RBRACE | COLON PythonBlock | Def Semi | Stat2 Semi | If | For | SET Expr
-	PythonBlock::= INDENT Block DEDENT | Stat
-	Stat2::= ID DOT Stat2 | ID AsgnOp | ID Func
-	Semi::= SEMI | NONE
-	Def::=	TYPE ID Set Expr | TYPE ID
-	AsgnOp::= Set Expr | INCR | DECR | NONE
-	Set::= SET | SETADD | SETSUB | SETMUL | SETDIV
-	Func::= LPAREN Comma RPAREN
-	Comma::= Expr Comma | COMMA Expr Comma | NONE
-	FuncDef::= FUNCDEF ID FuncDefArgs FuncDefType Block
-	FuncDefArgs::= LPAREN DefComma RPAREN | NONE
-	FuncDefType::= ARROW TYPE | NONE
-	DefComma::= Arg DefComma | COMMA Arg DefComma | NONE
-	Arg::= TYPE ID ArgDefault | ID ArgDefault
-	ArgDefault::= EQ Val | NONE
-	If::= IF Expr Stat Else
-	Else::=	ELSE Stat |	NONE
-	For::= FOR LPAREN Def SEMI Expr SEMI Stat2 RPAREN Stat
-	Expr::=	OpPrec5
-	OpPrec5::= OpPrec4 OpBool
-	OpBool::= AND OpPrec4 OpBool | OR OpPrec4 OpBool | NONE
-	OpPrec4::= OpPrec3 OpCmp
-	OpCmp::= EQ OpPrec3 OpCmp | LT OpPrec3 OpCmp | GT OpPrec3 OpCmp | LE OpPrec3 OpCmp | GE OpPrec3 OpCmp | NONE
-	OpPrec3::= OpPrec2 OpAS
-	OpAS::= PLUS OpPrec2 OpAS | MINUS OpPrec2 OpAS | NONE
-	OpPrec2::= OpPrec1 OpMD
-	OpMD::= MULT OpPrec1 OpMD | DIV OpPrec1 OpMD | NONE
-	OpPrec1::= Unit OpExp
-	OpExp::= EXP Unit OpExp | NONE
-	Unit::=	LPAREN Expr RPAREN TypeHint | Stat2 TypeHint | Val TypeHint
-	TypeHint::= LPAREN TYPE RPAREN | NONE
-	Val::= DECIMAL | INT | STRING
-")));
-
-//FUNCDEF is above COMMENT because otherwise #define would count as a comment
-static TOKEN_LIST: Lazy<Vec<(&str, &str, TokenAction)>> = Lazy::new(|| vec![
-	("FUNCDEF",    r"#define|function|fn", TokenAction::Identity),
-	("COMMENT",    r"(//.*)|(/\*(.|\n|\r)*?\*/)|(#.*)", TokenAction::Comment),
-	("STRING",    "(\".*?\")|('.*?')|(`.*?`)", TokenAction::Identity),
-	("IF",     r"if", TokenAction::Identity),
-	("ELSE",     r"else", TokenAction::Identity),
-	("FOR",     r"for", TokenAction::Identity),
-	("TYPE",    r"int|float|string|var", TokenAction::Identity),
-	("TRUE",    r"true|True|TRUE", TokenAction::Bool),
-	("FALSE",    r"false|False|FALSE", TokenAction::Bool),
-	("ARROW",    r"->", TokenAction::Identity),
-	("AND",    r"&&|and", TokenAction::Identity),
-	("OR",    r"\|\||or", TokenAction::Identity),
-	("EQ",    r"==", TokenAction::Identity),
-	("LE",    r"<=", TokenAction::Identity),
-	("GE",    r">=", TokenAction::Identity),
-	("LT",    r"<", TokenAction::Identity),
-	("GT",    r">", TokenAction::Identity),
-	("INCR",    r"\+\+", TokenAction::Identity),
-	("DECR",    r"--", TokenAction::Identity),
-	("SETADD",    r"\+=", TokenAction::Identity),
-	("SETSUB",    r"-=", TokenAction::Identity),
-	("SETMUL",    r"\*=", TokenAction::Identity),
-	("SETDIV",    r"/=", TokenAction::Identity),
-	("EXP",    r"\*\*|\^", TokenAction::Identity),
-	("DOT",    r"\.", TokenAction::Identity),
-	("PLUS",    r"\+", TokenAction::Identity),
-	("MINUS",    r"-", TokenAction::Identity),
-	("MULT",    r"\*", TokenAction::Identity),
-	("DIV",    r"/", TokenAction::Identity),
-	("SET",    r"=", TokenAction::Identity),
-	("SEMI",    r";", TokenAction::Identity),
-	("LPAREN",    r"\(", TokenAction::Identity),
-	("RPAREN",    r"\)", TokenAction::Identity),
-	("LBRACE",    r"\{", TokenAction::Identity),
-	("RBRACE",    r"\}", TokenAction::Identity),
-	("ASSIGN",    r"=", TokenAction::Identity),
-	("COLON",    r":", TokenAction::Identity),
-	("COMMA",    r",", TokenAction::Identity),
-	("ID",     r"[a-zA-Z_][a-zA-Z0-9_]*", TokenAction::Keywords),
-	("HNUM",    r"0x[0-9a-fA-F]+", TokenAction::HexNum),
-	("DECIMAL",    r"-?([0-9]*\.[0-9]+)", TokenAction::Identity),
-	("INT",    r"-?[0-9]+", TokenAction::Identity),
-	("NEWLINE",    r"\s*?\n", TokenAction::Newline),
-	("WHITESPACE",    r"\s*", TokenAction::Whitespace), //Creates INDENT and DEDENT for pythonic whitespace
-]);
+//GEL's grammar and tokens live in dialects/gel.grammar and dialects/gel.tokens, and GML's next to them.
+//build.rs runs them through grammar_generator ahead of time and writes out GEL_RULES, GEL_TABLE, GEL_TOKENS and the same for GML,
+//so a grammar conflict or a broken token regex stops the build instead of showing up when a program gets compiled.
+include!(concat!(env!("OUT_DIR"), "/dialect_tables.rs"));
+
+static GEL_DIALECT: Lazy<Dialect> = Lazy::new(|| Dialect::from_static(GEL_RULES, GEL_TABLE, GEL_TOKENS));
+//GameMaker/GML compatibility, used for .gml files and /gml in the console
+static GML_DIALECT: Lazy<Dialect> = Lazy::new(|| Dialect{gml_scoping: true, ..Dialect::from_static(GML_RULES, GML_TABLE, GML_TOKENS)});
+
+//A grammar and token list for the scanner and parser to compile with.
+//GEL's own is built in, and others can be loaded from files so variants of the language don't need a new build.
+#[derive(Clone)]
+pub struct Dialect {
+	pub grammar: ParseTable,
+	//the token list, built into a Lexer once so compiling doesn't have to build the scanner's regexes again
+	pub lexer: Lexer,
+	//whether programs compiled with it find variables the way GML does (see unwrap_values)
+	pub gml_scoping: bool,
+}
+
+impl Dialect {
+	pub fn new(grammar: ParseTable, tokens: Vec<(String, String, TokenAction)>) -> Dialect {
+		return Dialect{grammar, lexer: Lexer::new(&tokens), gml_scoping: false};
+	}
+
+	fn from_static(rules: &[(&str, &[&[(&str, bool, bool)]])], table: &[(&str, &[(&str, usize)])], tokens: &[(&str, &str, TokenAction)]) -> Dialect {
+		return Dialect::new(ParseTable::from_static(rules, table), tokens.iter().map(|(name, pattern, action)| (name.to_string(), pattern.to_string(), *action)).collect());
+	}
+}
+
+//The built-in dialect a file is written in, going by its extension
+pub fn dialect_for_file(filename: &str) -> &'static Dialect {
+	if filename.ends_with(".gml") {
+		return &GML_DIALECT;
+	}
+	return &GEL_DIALECT;
+}
+
+pub fn gml_dialect() -> &'static Dialect {
+	return &GML_DIALECT;
+}
+
+//Loads the dialect at path.grammar and path.tokens, written the same way as dialects/gel.grammar and dialects/gel.tokens.
+//Unlike GEL's, these get checked here, so a conflict or a broken token comes back as an error.
+pub fn load_dialect(path: &str) -> Result<Dialect, String> {
+	let grammar = match fs::read_to_string(format!("{}.grammar", path)) {
+		Ok(grammar) => grammar,
+		Err(error) => {
+			return Err(format!("Something went wrong reading {}.grammar: {}", path, error));
+		}
+	};
+	let tokens = match fs::read_to_string(format!("{}.tokens", path)) {
+		Ok(tokens) => tokens,
+		Err(error) => {
+			return Err(format!("Something went wrong reading {}.tokens: {}", path, error));
+		}
+	};
+	let grammar = grammar_generator(grammar).map_err(|error| format!("{}.grammar has a problem!\n{}", path, error))?;
+	let tokens = token_list_generator(&tokens).map_err(|error| format!("{}.tokens has a problem!\n{}", path, error))?;
+	return Ok(Dialect::new(grammar, tokens));
+}
 
 pub fn compile_file(filename: &str) -> Program {
+	return compile_file_with(filename, dialect_for_file(filename));
+}
+
+pub fn compile_file_with(filename: &str, dialect: &Dialect) -> Program {
+	match try_compile_file_with(filename, dialect) {
+		Ok(program) => {
+			return program;
+		}
+		Err(error) => {
+			let mut program = Program::new();
+			program.errors.push(error);
+			//still watched, so saving a fix loads it
+			if filename != "" {
+				program.source = Some(Source{files: vec![(filename.to_string(), modified(filename))], dialect: None});
+			}
+			return program;
+		}
+	}
+}
+
+//Same as compile_file, but hands back errors instead of an empty program with the error in its errors
+pub fn try_compile_file(filename: &str) -> Result<Program, String> {
+	return try_compile_file_with(filename, dialect_for_file(filename));
+}
+
+pub fn try_compile_file_with(filename: &str, dialect: &Dialect) -> Result<Program, String> {
 	
 	//Check to make sure we have a file to read
 	if filename == "" {
-		return Program::new();
+		return Ok(Program::new());
 	}
 
-	//let filename = &args[1];
+	//Already compiled, no need to go through the scanner and parser
+	if filename.ends_with(".gelc") {
+		let mut program = load_program(filename)?;
+		program.source = Some(Source{files: vec![(filename.to_string(), modified(filename))], dialect: None});
+		return Ok(program);
+	}
 	
 	match fs::read_to_string(filename) {
 		Ok(input) => {
-			return compile(input);
+			//imports are found next to the file first
+			let mut modules = Modules::new();
+			modules.start(filename);
+			let dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+			let (mut program, _) = compile_source(input, dialect, dir, &mut modules)?;
+			let mut files = vec![filename.to_string()];
+			files.extend(modules.files().iter().map(|path| path.display().to_string()));
+			let files = files.into_iter().map(|file| {
+				let time = modified(&file);
+				return (file, time);
+			}).collect();
+			program.source = Some(Source{files, dialect: None});
+			return Ok(program);
 		}
 		Err(error) => {
-			let mut program = Program::new();
-			program.log.push(format!("Something went wrong reading the file: {}", error));
-			return program;
+			return Err(format!("Something went wrong reading the file: {}", error));
 		}
 	}
 }
 
-pub fn compile(input: String) -> Program {
-	let tokens = scanner(input, &TOKEN_LIST);
-	let ast = match parser(tokens, &GEL_GRAMMAR) {
-		Ok(ast) => {ast},
-		Err(error) => {
-			let mut program = Program::new();
-			program.log.push(error);
-			return program;
-		}
-	};
+//When a file was last changed, or None if it can't be read right now (like while it's being saved)
+pub fn modified(filename: &str) -> Option<SystemTime> {
+	return fs::metadata(filename).and_then(|metadata| metadata.modified()).ok();
+}
+
+//Only the optimizer tests parse without compiling
+#[cfg(test)]
+pub fn parse(input: String) -> Result<ASTNode, String> {
+	return parse_with(input, &GEL_DIALECT);
+}
+
+pub fn parse_with(input: String, dialect: &Dialect) -> Result<ASTNode, String> {
+	return parser(dialect.lexer.tokens(input), &dialect.grammar);
+}
+
+pub fn try_compile(input: String) -> Result<Program, String> {
+	return try_compile_with(input, &GEL_DIALECT);
+}
+
+pub fn try_compile_with(input: String, dialect: &Dialect) -> Result<Program, String> {
+	return compile_source(input, dialect, Path::new(""), &mut Modules::new()).map(|(program, _)| program);
+}
+
+//Compiles input and links in what it imports, which are looked for in dir first.
+//Also hands back the names of the functions input defines itself, for when it's being imported as a module.
+pub fn compile_source(input: String, dialect: &Dialect, dir: &Path, modules: &mut Modules) -> Result<(Program, Vec<String>), String> {
+	let ast = parse_with(input, dialect)?;
+	let imports = find_imports(&ast);
+	let mut program = compile_ast(ast)?;
+	program.gml_scoping = dialect.gml_scoping;
+	let exports = program.functions.keys().filter(|name| *name != "").cloned().collect();
+	for import in &imports {
+		modules.link(&mut program, import, dir)?;
+	}
+	return Ok((program, exports));
+}
+
+//Everything after parsing is the same for every dialect
+fn compile_ast(ast: ASTNode) -> Result<Program, String> {
 	//println!("{:#?}", ast);
 	let mut optimized_ast = optimize_ast(ast);
 	//let opcodes = linearize_ast(optimized_ast, linearize as fn(&mut ASTNode, &mut Vec<Opcode>));
-	let program = linearize_ast(&mut optimized_ast);
+	let mut program = linearize_ast(&mut optimized_ast)?;
 	//println!("{:#?}", program);
-	//Make control flow graph?
-	//let optimized_opcodes = optimize_opcodes(opcodes);
+	optimize_opcodes(&mut program);
 
-	return program;
-}
\ No newline at end of file
+	return Ok(program);
+}
@@ -1,2 +1,3 @@
 pub mod token_actions;
-pub mod scanner;
\ No newline at end of file
+pub mod scanner;
+pub mod offside;
\ No newline at end of file
@@ -0,0 +1,92 @@
+use std::cmp::Ordering;
+
+//Tabs go out to the next multiple of this
+pub const TAB_WIDTH: usize = 8;
+
+//The Offside Rule, for COLON blocks written python style
+//When a line ends with : and the next line is indented further, the block starts (INDENT), and it ends (DEDENT) once a line is
+//indented back out to where the : line was. Indents anywhere else don't mean anything, so {} code can be indented however it likes.
+//Only lines with a token on them count, so blank lines and lines that are just a comment can be indented any way.
+pub struct Offside {
+	//(the block's indent, the indent of the line that started it)
+	blocks: Vec<(Indent, Indent)>,
+	line_indent: Indent,
+	opens_block: bool,
+}
+
+//How far in a line starts, counting tabs as TAB_WIDTH and as 1 space.
+//If the two don't agree on which of two lines is further in, it depends on the editor, so it's an error.
+#[derive(Clone, Copy, Default)]
+struct Indent {
+	width: usize,
+	tabs_as_one: usize,
+}
+
+impl Offside {
+	pub fn new() -> Offside {
+		return Offside{blocks: vec![], line_indent: Indent::default(), opens_block: false};
+	}
+
+	//Called with the whitespace in front of the first token on a line, before the token itself
+	pub fn line_start(&mut self, whitespace: &str, line: i32) -> Result<Vec<(String, String, i32)>, String> {
+		let indent = measure(whitespace);
+		let mut ret_val = vec![];
+		if self.opens_block {
+			if compare(indent, self.line_indent, line)? != Ordering::Greater {
+				return Err(format!("Whoops, indentation error!\nLine {} needs to be indented, since the line before it ends with a :", line));
+			}
+			ret_val.push(("INDENT".to_string(), whitespace.to_string(), -1));
+			self.blocks.push((indent, self.line_indent));
+		} else {
+			while let Some((block, opener)) = self.blocks.last().copied() {
+				if compare(indent, block, line)? != Ordering::Less {
+					break;
+				}
+				ret_val.push(("DEDENT".to_string(), whitespace.to_string(), -1));
+				self.blocks.pop();
+				//out of the block, but not back as far as the line that started it
+				if compare(indent, opener, line)? == Ordering::Greater {
+					return Err(format!("Whoops, indentation error!\nThe unindent on line {} doesn't match any outer indentation level", line));
+				}
+			}
+		}
+		self.line_indent = indent;
+		return Ok(ret_val);
+	}
+
+	//Called for every token that isn't whitespace, a newline or a comment
+	pub fn token(&mut self, name: &str) {
+		self.opens_block = name == "COLON";
+	}
+
+	//Closes the blocks that are still open when the input ends
+	pub fn finish(&mut self) -> Vec<(String, String, i32)> {
+		return self.blocks.drain(..).map(|_| ("DEDENT".to_string(), String::new(), -1)).collect();
+	}
+}
+
+fn measure(whitespace: &str) -> Indent {
+	let mut indent = Indent::default();
+	for character in whitespace.chars() {
+		match character {
+			'\t' => {
+				indent.width = (indent.width / TAB_WIDTH + 1) * TAB_WIDTH;
+				indent.tabs_as_one += 1;
+			}
+			' ' => {
+				indent.width += 1;
+				indent.tabs_as_one += 1;
+			}
+			_ => {}
+		}
+	}
+	return indent;
+}
+
+fn compare(left: Indent, right: Indent, line: i32) -> Result<Ordering, String> {
+	let ordering = left.width.cmp(&right.width);
+	if ordering != left.tabs_as_one.cmp(&right.tabs_as_one) {
+		return Err(format!("Whoops, indentation error!\nLine {} mixes tabs and spaces in a way that depends on how wide a tab is", line));
+	}
+	return Ok(ordering);
+}
@@ -1,45 +1,392 @@
+use std::collections::{HashMap, VecDeque};
 use regex::Regex;
-use once_cell::sync::Lazy;
-use crate::datatypes::TokenAction;
+use crate::parse_table::TokenAction;
 use crate::scanner::token_actions::token_actions;
+use crate::scanner::offside::Offside;
 
-//An Exact Match Scanner
-pub fn scanner(inputstr: String, token_list: &Lazy<Vec<(&str, &str, TokenAction)>>) -> Vec<(String, String, i32)> {
-	let mut ret_val: Vec<(String, String, i32)> = vec![];
-    let mut regex_builder = "".to_owned();
-	
-	for t in token_list.iter(){
-		regex_builder.push_str("(?P<");
-		regex_builder.push_str(t.0);
-		regex_builder.push_str(">");
-		regex_builder.push_str(t.1);
-		regex_builder.push_str(")|");
-	}
-	
-	if regex_builder.len() > 0 {
-		regex_builder.pop();
-	}
-	
-	//Our iterator of tokens
-	let re = Regex::new(&regex_builder).unwrap();
-	let tokens = re.captures_iter(inputstr.as_str());
-	let mut line = 1;
-	let mut new_line = true;
-	let mut whitespace_tracker = vec![];
-    for tok in tokens {
-        for t in token_list.iter() {
-			if tok.name(t.0) != None {
-				let prev_line = line;
-				let mut token = token_actions((t.0.to_owned(), tok.name(t.0).unwrap().as_str().to_owned(), line), t.2.to_owned(), &mut line, new_line, &mut whitespace_tracker);
-				if prev_line != line {
-					new_line = true;
+//A Maximal Munch Scanner
+//Every token is tried where the last one ended, and the longest match wins (ties go to whichever is higher up in the token list),
+//so <= never comes out as < then =.
+//Words in a token's regex (if, int, true...) are taken out into a keyword table, and the token with TokenAction::Keywords (ID)
+//looks them up after it matches a whole word, so iffy is one ID instead of IF then fy.
+//The regexes are built once when the Lexer is made (each Dialect keeps one), and tokens() hands tokens out as the parser asks for them.
+#[derive(Clone)]
+pub struct Lexer {
+	regexes: Vec<(String, Regex, TokenAction)>,
+	keywords: HashMap<String, (String, TokenAction)>,
+}
+
+impl Lexer {
+	pub fn new(token_list: &[(String, String, TokenAction)]) -> Lexer {
+		let (patterns, keywords) = split_keywords(token_list);
+		return Lexer {
+			//Anchored so they only match right where the scanner is
+			regexes: patterns.iter().map(|(name, pattern, action)| (name.to_string(), Regex::new(&format!("^(?:{})", pattern)).unwrap(), *action)).collect(),
+			keywords: keywords.into_iter().map(|(word, (name, action))| (word, (name.to_string(), action))).collect(),
+		};
+	}
+
+	pub fn tokens(&self, input: String) -> Tokens<'_> {
+		return Tokens {
+			lexer: self,
+			input,
+			position: 0,
+			line: 1,
+			new_line: true,
+			indent: String::new(),
+			offside: Offside::new(),
+			pending: VecDeque::new(),
+		};
+	}
+}
+
+//The tokens in one input, scanned a token at a time
+pub struct Tokens<'a> {
+	lexer: &'a Lexer,
+	input: String,
+	position: usize,
+	line: i32,
+	new_line: bool,
+	//the whitespace at the start of the line, which only counts once the line turns out to have a token on it
+	indent: String,
+	offside: Offside,
+	//one match can turn into a few tokens (INDENTs and DEDENTs, or a string with {} in it)
+	pending: VecDeque<(String, String, i32)>,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+	type Item = (String, String, i32);
+
+	fn next(&mut self) -> Option<(String, String, i32)> {
+		while self.pending.len() == 0 {
+			if self.position >= self.input.len() {
+				self.pending.extend(self.offside.finish());
+				if self.pending.len() == 0 {
+					return None;
+				}
+				continue;
+			}
+			let rest = &self.input[self.position..];
+			let mut longest: Option<(&str, usize, TokenAction)> = None;
+			for (name, regex, action) in &self.lexer.regexes {
+				if let Some(found) = regex.find(rest) {
+					if found.end() > longest.map_or(0, |(_, length, _)| length) {
+						longest = Some((name, found.end(), *action));
+					}
+				}
+			}
+			let (mut name, length, mut action) = match longest {
+				Some(longest) => longest,
+				None => {
+					//nothing matches here, so skip the character
+					self.position += rest.chars().next().unwrap().len_utf8();
+					continue;
+				}
+			};
+			let text = rest[..length].to_owned();
+			if let TokenAction::Keywords = action {
+				if let Some((keyword, keyword_action)) = self.lexer.keywords.get(&text) {
+					name = keyword;
+					action = *keyword_action;
+				}
+			}
+			let name = name.to_owned();
+			self.position += length;
+
+			match action {
+				TokenAction::Whitespace => {
+					if self.new_line {
+						self.indent = text.to_owned();
+					}
+				}
+				TokenAction::Newline => {
+					self.new_line = true;
+					self.indent = String::new();
+				}
+				TokenAction::Comment => {}
+				_ => {
+					if self.new_line {
+						match self.offside.line_start(&self.indent, self.line) {
+							Ok(indents) => self.pending.extend(indents),
+							Err(error) => {
+								//the parser stops at an ERROR, so there's no point scanning the rest
+								self.pending.push_back(("ERROR".to_string(), error, self.line));
+								self.position = self.input.len();
+								self.offside = Offside::new();
+								break;
+							}
+						}
+						self.new_line = false;
+					}
+					self.offside.token(&name);
+				}
+			}
+
+			let token = token_actions((name.to_owned(), text.to_owned(), self.line), action, &mut self.line);
+			if let TokenAction::String = action {
+				for token in token {
+					if token.0 == name {
+						self.pending.extend(string_tokens(token, self.lexer));
+					} else {
+						self.pending.push_back(token);
+					}
+				}
+				//a """ string can go over a few lines, but it's still the line it started on as far as indents go
+				self.line += text.matches('\n').count() as i32;
+			} else {
+				self.pending.extend(token);
+			}
+		}
+		return self.pending.pop_front();
+	}
+}
+
+//Splits every token's regex into the words in it, which go in the keyword table, and the regex that's left.
+//Nothing is split out if there isn't a Keywords token to look the words up.
+fn split_keywords(token_list: &[(String, String, TokenAction)]) -> (Vec<(&str, String, TokenAction)>, HashMap<String, (&str, TokenAction)>) {
+	let mut patterns = vec![];
+	let mut keywords = HashMap::new();
+	let has_keywords = token_list.iter().any(|(_, _, action)| matches!(action, TokenAction::Keywords));
+	let word = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+	for (name, pattern, action) in token_list {
+		if !has_keywords || matches!(action, TokenAction::Keywords) {
+			patterns.push((name.as_str(), pattern.to_owned(), *action));
+			continue;
+		}
+		let mut rest = vec![];
+		for alternative in split_alternatives(pattern) {
+			if word.is_match(alternative) {
+				//a word that's already a keyword keeps the token that's higher up
+				keywords.entry(alternative.to_string()).or_insert((name.as_str(), *action));
+			} else {
+				rest.push(alternative);
+			}
+		}
+		if rest.len() > 0 {
+			patterns.push((name.as_str(), rest.join("|"), *action));
+		}
+	}
+	return (patterns, keywords);
+}
+
+//Works out the escapes in a string token, leaving it as "text" so linearize only has to take the quotes off.
+//If it has {expr}s in it, it becomes ("text" + (expr) + "text"...) instead, scanned with the same Lexer so any dialect can do it.
+//r"..." strings are left as they are.
+fn string_tokens(token: (String, String, i32), lexer: &Lexer) -> Vec<(String, String, i32)> {
+	let (name, text, line) = token;
+	let raw = text.starts_with('r') || text.starts_with('R');
+	let quoted = if raw { &text[1..] } else { &text[..] };
+	let quote_length = if quoted.len() >= 6 && (quoted.starts_with("\"\"\"") || quoted.starts_with("'''")) { 3 } else { 1 };
+	let mut body = &quoted[quote_length..quoted.len() - quote_length];
+	if quote_length == 3 {
+		//so the text can start on the line after the """
+		body = body.strip_prefix("\r\n").or(body.strip_prefix('\n')).unwrap_or(body);
+	}
+	let literal = |value: &str| (name.to_owned(), format!("\"{}\"", value), line);
+	if raw {
+		return vec![literal(body)];
+	}
+
+	//(is an expression, text)
+	let mut parts: Vec<(bool, String)> = vec![(false, String::new())];
+	let mut chars = body.chars().peekable();
+	while let Some(character) = chars.next() {
+		if parts.last().unwrap().0 {
+			parts.push((false, String::new()));
+		}
+		let current = &mut parts.last_mut().unwrap().1;
+		match character {
+			'\\' => match chars.next() {
+				Some('n') => current.push('\n'),
+				Some('t') => current.push('\t'),
+				Some('r') => current.push('\r'),
+				Some('0') => current.push('\0'),
+				Some('u') => {
+					//\u{1F600} or \u00e9
+					let digits: String = if chars.peek() == Some(&'{') {
+						chars.next();
+						let digits = chars.by_ref().take_while(|digit| *digit != '}').collect();
+						digits
+					} else {
+						chars.by_ref().take(4).collect()
+					};
+					match u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32) {
+						Some(unicode) => current.push(unicode),
+						None => current.push_str(&format!("\\u{}", digits)),
+					}
+				}
+				Some(other) if "\"'`{}\\".contains(other) => current.push(other),
+				Some(other) => {
+					current.push('\\');
+					current.push(other);
+				}
+				None => current.push('\\'),
+			},
+			'{' => {
+				let mut depth = 1;
+				let mut expression = String::new();
+				while let Some(inner) = chars.next() {
+					match inner {
+						'{' => depth += 1,
+						'}' => depth -= 1,
+						_ => {}
+					}
+					if depth == 0 {
+						break;
+					}
+					expression.push(inner);
+				}
+				if depth == 0 && expression.trim() == "" {
+					//there's nothing to put in, and otherwise the parser would complain about a ) it wasn't expecting
+					return vec![("ERROR".to_string(), format!("Empty interpolation {{}} in the string on line {} (write \\{{\\}} for the braces themselves)", line), line)];
+				} else if depth == 0 {
+					parts.push((true, expression));
 				} else {
-					new_line = false;
+					//no closing }, so it was just text
+					current.push('{');
+					current.push_str(&expression);
 				}
-				ret_val.append(&mut token);
 			}
+			_ => current.push(character),
+		}
+	}
+
+	if parts.len() == 1 {
+		return vec![literal(&parts[0].1)];
+	}
+	let on_line = |tokens: Vec<(String, String, i32)>| tokens.into_iter().map(|(name, text, _)| (name, text, line)).collect::<Vec<(String, String, i32)>>();
+	let punctuation = on_line(lexer.tokens("(+)".to_string()).collect());
+	let (open, plus, close) = (&punctuation[0], &punctuation[1], &punctuation[2]);
+	//starts with a string, even an empty one, so + joins instead of adding
+	let mut ret_val = vec![open.to_owned(), literal(&parts[0].1)];
+	for (expression, part) in &parts[1..] {
+		if *expression {
+			ret_val.push(plus.to_owned());
+			ret_val.push(open.to_owned());
+			ret_val.append(&mut on_line(lexer.tokens(part.to_owned()).collect()));
+			ret_val.push(close.to_owned());
+		} else if part.len() > 0 {
+			ret_val.push(plus.to_owned());
+			ret_val.push(literal(part));
+		}
+	}
+	ret_val.push(close.to_owned());
+	return ret_val;
+}
+
+//The parts of a regex between its top level |s
+fn split_alternatives(pattern: &str) -> Vec<&str> {
+	let mut ret_val = vec![];
+	let mut depth = 0;
+	let mut in_class = false;
+	let mut escaped = false;
+	let mut start = 0;
+	for (index, character) in pattern.char_indices() {
+		if escaped {
+			escaped = false;
+			continue;
+		}
+		match character {
+			'\\' => escaped = true,
+			'[' => in_class = true,
+			']' => in_class = false,
+			'(' if !in_class => depth += 1,
+			')' if !in_class => depth -= 1,
+			'|' if !in_class && depth == 0 => {
+				ret_val.push(&pattern[start..index]);
+				start = index + 1;
+			}
+			_ => {}
 		}
-    }
-	
-	return ret_val
-}
\ No newline at end of file
+	}
+	ret_val.push(&pattern[start..]);
+	return ret_val;
+}
+
+#[cfg(test)]
+fn scanner(input: &str) -> Vec<(String, String, i32)> {
+	return crate::parser::parser_setup::load_dialect("dialects/gel").unwrap().lexer.tokens(input.to_string()).collect();
+}
+
+#[cfg(test)]
+fn scan_types(input: &str) -> Vec<String> {
+	return scanner(input).into_iter().map(|token| token.0).collect();
+}
+
+#[test]
+fn keyword_test(){
+	//identifiers that start with a keyword stay identifiers
+	assert!(scan_types("iffy format integer fn_name") == vec!["ID", "ID", "ID", "ID"]);
+	assert!(scan_types("if for int function and") == vec!["IF", "FOR", "TYPE", "FUNCDEF", "AND"]);
+	//Bool keywords still go through their action
+	assert!(scanner("true") == vec![("INT".to_string(), "1".to_string(), 1)]);
+	assert!(split_alternatives(r"(//.*)|(a|b)|[|]|\||x") == vec![r"(//.*)", "(a|b)", "[|]", r"\|", "x"]);
+}
+
+#[test]
+fn maximal_munch_test(){
+	assert!(scan_types("a<=b") == vec!["ID", "LE", "ID"]);
+	assert!(scan_types("a-1") == vec!["ID", "MINUS", "INT"]);
+	assert!(scan_types("x **= 1.5") == vec!["ID", "EXP", "SET", "DECIMAL"]);
+	//#define isn't a comment, but # followed by anything other than a word still is
+	assert!(scan_types("#define init # comment\nx") == vec!["FUNCDEF", "ID", "ID"]);
+}
+
+#[test]
+fn numeric_literal_test(){
+	let scanned: Vec<(String, String)> = scanner("0xFF 0b1010 1_000_000 1.5e3 2E-2 .5 #ff8800").into_iter().map(|token| (token.0, token.1)).collect();
+	let expected = [("INT", "255"), ("INT", "10"), ("INT", "1000000"), ("DECIMAL", "1500"), ("DECIMAL", "0.02"), ("DECIMAL", ".5"), ("COLOR", "#ff8800")];
+	assert!(scanned == expected.iter().map(|(typ, text)| (typ.to_string(), text.to_string())).collect::<Vec<(String, String)>>());
+
+	let program = crate::parser::parser_setup::try_compile("print(#ff000080)".to_string()).unwrap();
+	let half = rust_decimal::Decimal::from(128) / rust_decimal::Decimal::from(255);
+	let red = crate::datatypes::Data::Color(1.into(), 0.into(), 0.into(), half);
+	assert!(program.functions[""].opcodes.iter().any(|op| op.data == red || op.data2 == red));
+
+	//numbers that don't fit in a Decimal (or aren't numbers) are compile errors with the line
+	for number in ["0x_", "0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF", "1e99", "100000000000000000000000000000000"] {
+		let error = crate::parser::parser_setup::try_compile(format!("x = 1\nprint({})", number)).err().unwrap();
+		assert!(error.contains(&format!("The number {} on line 2", number)));
+	}
+}
+
+#[test]
+fn lexer_test(){
+	//the parser pulls tokens one at a time, so they don't all have to be scanned up front
+	let lexer = crate::parser::parser_setup::load_dialect("dialects/gel").unwrap().lexer;
+	let mut tokens = lexer.tokens("if x\ny".to_string());
+	assert!(tokens.next().unwrap().0 == "IF");
+	assert!(tokens.next().unwrap().0 == "ID");
+	assert!(tokens.next() == Some(("ID".to_string(), "y".to_string(), 2)));
+	assert!(tokens.next() == None);
+	//the same Lexer can scan as many inputs as it likes
+	assert!(lexer.tokens("\"{a}\"".to_string()).count() == 7);
+}
+
+#[test]
+fn string_literal_test(){
+	let scanned: Vec<(String, String)> = scanner("\"a\\\"b\" r\"\\n\" \"x{y}\"").into_iter().map(|token| (token.0, token.1)).collect();
+	let expected = [("STRING", "\"a\"b\""), ("STRING", "\"\\n\""), ("LPAREN", "("), ("STRING", "\"x\""), ("PLUS", "+"), ("LPAREN", "("), ("ID", "y"), ("RPAREN", ")"), ("RPAREN", ")")];
+	assert!(scanned == expected.iter().map(|(typ, text)| (typ.to_string(), text.to_string())).collect::<Vec<(String, String)>>());
+	//lines inside a """ string still count
+	assert!(scanner("\"\"\"a\nb\"\"\"\nx").last().unwrap().2 == 3);
+	//{} with nothing in it is an error, but escaped braces are just text
+	assert!(crate::parser::parser_setup::try_compile("x = 1\nprint(\"a{ }b\")".to_string()).err().unwrap().contains("Empty interpolation {} in the string on line 2"));
+	assert!(scanner("\"\\{\\}\"") == vec![("STRING".to_string(), "\"{}\"".to_string(), 1)]);
+}
+
+#[test]
+fn offside_test(){
+	assert!(scan_types("if x:\n\ta\n\n  \n\t// comment\n// comment\n\tb\nc") == vec!["IF", "ID", "COLON", "INDENT", "ID", "ID", "DEDENT", "ID"]);
+	//blocks still open at the end get closed, and only lines after a : start one
+	assert!(scan_types("if x:\n\tif y:\n\t\ta\n\t\t\tb") == vec!["IF", "ID", "COLON", "INDENT", "IF", "ID", "COLON", "INDENT", "ID", "ID", "DEDENT", "DEDENT"]);
+	assert!(scan_types("{\n\t\ta\n\tb\n}") == vec!["LBRACE", "ID", "ID", "RBRACE"]);
+
+	let compile = |source: &str| crate::parser::parser_setup::try_compile(source.to_string());
+	assert!(compile("if 1:\n    a = 1\n  b = 2").err().unwrap().contains("doesn't match any outer indentation level"));
+	assert!(compile("if 1:\n        a = 1\n\tb = 2").err().unwrap().contains("tabs and spaces"));
+	assert!(compile("if 1:\nb = 2").err().unwrap().contains("needs to be indented"));
+	let mut program = compile("if 0:\n\tprint(1)\n\tprint(2)\nprint(3)").unwrap();
+	crate::interpreter::interpreter::interpret_program(&mut program, "").unwrap();
+	assert!(program.log == vec!["3"]);
+}
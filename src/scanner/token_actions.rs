@@ -1,30 +1,10 @@
-use crate::datatypes::TokenAction;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::{FromPrimitive, FromStr};
+use crate::parse_table::TokenAction;
 
-pub fn token_actions(token: (String, String, i32), action: TokenAction, line_counter: &mut i32, new_line: bool, whitespace_tracker: &mut Vec<usize>) -> Vec<(String, String, i32)>{
+//INDENT and DEDENT come from the scanner's Offside, the rest of the token actions are here
+pub fn token_actions(token: (String, String, i32), action: TokenAction, line_counter: &mut i32) -> Vec<(String, String, i32)>{
 	let mut ret_val = vec![];
-	if new_line {
-		match action {
-			TokenAction::Whitespace => {
-				let length = token.1.len();
-				while whitespace_tracker.len() > 0 {
-					if length > *whitespace_tracker.last().unwrap() {
-						ret_val.push(("INDENT".to_string(), token.1.to_owned(), -1));
-						whitespace_tracker.push(length);
-						break;
-					}else if length < *whitespace_tracker.last().unwrap() {
-						ret_val.push(("DEDENT".to_string(), token.1.to_owned(), -1));
-						whitespace_tracker.pop();
-					}
-				}
-			},
-			_ => {
-				while whitespace_tracker.len() > 0 {
-					ret_val.push(("DEDENT".to_string(), token.1.to_owned(), -1));
-					whitespace_tracker.pop();
-				}
-			}
-		}
-	}
 	match action {
 		TokenAction::Identity => ret_val.push(token),
 		TokenAction::Keywords => ret_val.push(token),
@@ -34,12 +14,43 @@ pub fn token_actions(token: (String, String, i32), action: TokenAction, line_cou
 				"FALSE" => ret_val.push(("INT".to_string(), "0".to_string(), token.2)),
 				_ => {}
 			},
+		//0xFF and 0b1010 become the INT they stand for
+		TokenAction::HexNum => ret_val.push(match radix_number(&token.1[2..], 16) {
+			Some(value) => ("INT".to_string(), value, token.2),
+			None => number_error(&token),
+		}),
+		TokenAction::BinNum => ret_val.push(match radix_number(&token.1[2..], 2) {
+			Some(value) => ("INT".to_string(), value, token.2),
+			None => number_error(&token),
+		}),
+		//1_000_000 loses its underscores and 1.5e3 gets written out as 1500, so linearize can read them
+		TokenAction::Number => {
+			let digits = token.1.replace("_", "").to_lowercase();
+			let value = if digits.contains('e') { Decimal::from_scientific(&digits) } else { Decimal::from_str(&digits) };
+			match value {
+				Ok(value) if digits.contains('e') => ret_val.push((token.0, value.normalize().to_string(), token.2)),
+				Ok(_) => ret_val.push((token.0, digits, token.2)),
+				Err(_) => ret_val.push(number_error(&token)),
+			}
+		},
+		//the scanner takes care of escapes and {} in strings, since that needs the rest of the token list
+		TokenAction::String => ret_val.push(token),
 		TokenAction::Comment => {},
 		TokenAction::Newline => {
 			*line_counter = *line_counter + 1;
 		},
 		TokenAction::Whitespace => {},
-		_ => ret_val.push(token)
 	}
 	return ret_val;
+}
+
+//Numbers are Decimals once they're compiled, so anything bigger than one can hold doesn't work either
+fn radix_number(digits: &str, radix: u32) -> Option<String> {
+	let value = u128::from_str_radix(&digits.replace("_", ""), radix).ok()?;
+	return Decimal::from_u128(value).map(|value| value.to_string());
+}
+
+//The parser stops at an ERROR, with its text as the message
+fn number_error(token: &(String, String, i32)) -> (String, String, i32) {
+	return ("ERROR".to_string(), format!("The number {} on line {} is too big or isn't a number", token.1, token.2), token.2);
 }
\ No newline at end of file
@@ -0,0 +1,26 @@
+#define init{
+	name = "program"
+	total = 100
+	global.count = 0
+	var a = 1, b = 2
+	add(a, b)
+	print(total)
+	first = instance_create(10, 20, "enemy")
+	instance_create(30, 40, "enemy")
+	with("enemy") {
+		global.count = global.count + 1
+		print(x + hp)
+	}
+	with(first) print(other.name)
+	print(global.count)
+}
+
+#define add{
+	print(argument_count)
+	var total = argument0 + argument1
+	print(total)
+}
+
+#define enemy_create{
+	hp = argument0 / 10
+}